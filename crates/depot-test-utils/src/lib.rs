@@ -4,7 +4,9 @@ use anyhow::{Result, ensure};
 use std::{
     fs,
     path::{Path, PathBuf},
-    process::Command,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
 };
 
 use either::Either;
@@ -20,10 +22,26 @@ pub struct CommandOutput {
     stderr: String,
 }
 
+impl CommandOutput {
+    pub fn stdout(&self) -> &str {
+        &self.stdout
+    }
+}
+
 fn new_cmd(s: impl AsRef<str>) -> String {
     format!("{} --prefer-offline", s.as_ref())
 }
 
+/// A depot process running in the background, e.g. in watch mode. It is killed when dropped.
+pub struct BackgroundDepot(Child);
+
+impl Drop for BackgroundDepot {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
 impl ProjectBuilder {
     pub fn new() -> Self {
         let tmpdir = TempDir::new().unwrap();
@@ -85,6 +103,33 @@ impl ProjectBuilder {
         self.maybe_depot(cmd).unwrap()
     }
 
+    pub fn spawn_depot(&self, cmd: impl AsRef<str>) -> BackgroundDepot {
+        let mut process = Command::new(depot_exe());
+        process.current_dir(self.root());
+        process.args(shlex::split(cmd.as_ref()).unwrap());
+        process.stdout(Stdio::null()).stderr(Stdio::null());
+        BackgroundDepot(process.spawn().unwrap())
+    }
+
+    /// Polls until `path` exists and its contents satisfy `f`, panicking after `timeout`.
+    pub fn wait_for_file(
+        &self,
+        path: impl AsRef<Path>,
+        timeout: Duration,
+        f: impl Fn(&str) -> bool,
+    ) {
+        let path = self.root().join(path);
+        let start = Instant::now();
+        while !fs::read_to_string(&path).is_ok_and(|contents| f(&contents)) {
+            assert!(
+                start.elapsed() < timeout,
+                "timed out waiting for: {}",
+                path.display()
+            );
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
     pub fn read(&self, path: impl AsRef<Path>) -> String {
         fs::read_to_string(self.root().join(path)).unwrap()
     }
@@ -0,0 +1,156 @@
+//! The record of files produced by a package build, written to `dist/.depot-manifest.json`.
+//!
+//! Later steps like size reporting and `depot clean` read this record rather than walking `dist/`.
+//! It is only written after a successful build that isn't in watch mode, and removed when the
+//! next build starts, so its absence means `dist/` may hold outputs that it wouldn't list.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    utils,
+    workspace::{DEPOT_VERSION, package::Package},
+};
+
+const MANIFEST_FILE: &str = ".depot-manifest.json";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Artifact {
+    pub size: u64,
+
+    /// SHA-256 digest of the file's contents.
+    pub hash: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ArtifactManifest {
+    pub depot_version: String,
+    pub profile: String,
+
+    /// SHA-256 digest of the package's input files at the time of the build.
+    pub input_fingerprint: String,
+
+    /// Every file in `dist/`, keyed by path relative to the package root.
+    pub files: BTreeMap<String, Artifact>,
+}
+
+fn manifest_path(pkg: &Package) -> PathBuf {
+    pkg.root.join("dist").join(MANIFEST_FILE)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("Failed to read: {}", path.display()))
+}
+
+fn rel_path(pkg: &Package, path: &Path) -> Result<String> {
+    Ok(path
+        .strip_prefix(&pkg.root)?
+        .display()
+        .to_string()
+        .replace('\\', "/"))
+}
+
+impl ArtifactManifest {
+    /// Records the current contents of `pkg`'s `dist/` directory.
+    pub fn generate(pkg: &Package) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        for path in pkg.glob_files(&["dist/**".to_string()])? {
+            if path == manifest_path(pkg) {
+                continue;
+            }
+            let contents = read(&path)?;
+            let artifact = Artifact {
+                size: contents.len() as u64,
+                hash: format!("{:x}", Sha256::digest(&contents)),
+            };
+            files.insert(rel_path(pkg, &path)?, artifact);
+        }
+
+        let mut inputs = pkg.all_files().collect::<Vec<_>>();
+        inputs.sort();
+        // Each field is prefixed by its length to keep the boundaries between files unambiguous.
+        let mut hasher = Sha256::new();
+        let mut update = |bytes: &[u8]| {
+            hasher.update((bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        };
+        for path in inputs {
+            update(rel_path(pkg, &path)?.as_bytes());
+            update(&read(&path)?);
+        }
+
+        Ok(ArtifactManifest {
+            depot_version: DEPOT_VERSION.to_string(),
+            profile: pkg.workspace().profile.name.clone(),
+            input_fingerprint: format!("{:x}", hasher.finalize()),
+            files,
+        })
+    }
+
+    pub fn save(&self, pkg: &Package) -> Result<()> {
+        let path = manifest_path(pkg);
+        utils::create_dir_if_missing(path.parent().unwrap())?;
+        utils::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load(pkg: &Package) -> Result<Option<Self>> {
+        let path = manifest_path(pkg);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path)?;
+        let manifest = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid artifact manifest: {}", path.display()))?;
+        Ok(Some(manifest))
+    }
+
+    /// Removes `pkg`'s manifest, if any, since the build that is about to start may change `dist/`.
+    pub fn invalidate(pkg: &Package) -> Result<()> {
+        let path = manifest_path(pkg);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Removes the files listed in `pkg`'s manifest, then any directories in `dist/` left empty.
+///
+/// Without a manifest, e.g. after a watch build or a failed build, all of `dist/` is removed.
+pub fn remove(pkg: &Package) -> Result<()> {
+    let dist = pkg.root.join("dist");
+    let Some(manifest) = ArtifactManifest::load(pkg)? else {
+        return utils::remove_dir_all_if_exists(dist);
+    };
+
+    for file in manifest.files.keys() {
+        let path = pkg.root.join(file);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    fs::remove_file(manifest_path(pkg))?;
+
+    // Remove the deepest directories first, so their parents can become empty.
+    let mut dirs = ignore::WalkBuilder::new(&dist)
+        .standard_filters(false)
+        .build()
+        .filter_map(|entry| Some(entry.ok()?.into_path()))
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    for dir in dirs {
+        if fs::read_dir(&dir)?.next().is_none() {
+            fs::remove_dir(dir)?;
+        }
+    }
+
+    Ok(())
+}
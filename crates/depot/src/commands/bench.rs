@@ -0,0 +1,476 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result, anyhow, ensure};
+
+use super::{
+    build::{BuildArgs, BuildCommand},
+    stats::{self, Summary},
+};
+use crate::{
+    utils,
+    workspace::{
+        Command, CoreCommand, PackageCommand, Workspace,
+        package::{Package, PackageName},
+    },
+};
+
+/// Differences in mean time below this fraction are considered noise, even if significant.
+const NOISE_THRESHOLD: f64 = 0.02;
+
+/// Differences with a p-value below this are considered significant.
+const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+/// Run benchmarks via vitest
+#[derive(clap::Parser, Default, Debug)]
+pub struct BenchArgs {
+    /// Save the results as a baseline with this name, to compare later runs against
+    #[arg(long, value_name = "NAME")]
+    save_baseline: Option<String>,
+
+    /// Compare the results against a saved baseline, or else against the results of a git revision
+    #[arg(long, value_name = "NAME")]
+    baseline: Option<String>,
+
+    /// Exit with an error if any benchmark regressed compared to the baseline
+    #[arg(long, requires = "baseline")]
+    fail_on_regression: bool,
+
+    /// Additional arguments to pass to vitest
+    #[arg(last = true)]
+    pub vitest_args: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VitestBenchmark {
+    name: String,
+
+    /// Mean time per iteration in milliseconds.
+    mean: f64,
+    sd: f64,
+
+    #[serde(default)]
+    sample_count: Option<usize>,
+
+    #[serde(default)]
+    samples: Vec<f64>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VitestGroup {
+    full_name: String,
+    benchmarks: Vec<VitestBenchmark>,
+}
+
+#[derive(serde::Deserialize)]
+struct VitestFile {
+    groups: Vec<VitestGroup>,
+}
+
+/// The output of `vitest bench --outputJson`.
+#[derive(serde::Deserialize)]
+struct VitestBenchReport {
+    files: Vec<VitestFile>,
+}
+
+/// Statistics of each benchmark in a package, keyed by its full name.
+pub type BenchResults = BTreeMap<String, Summary>;
+
+impl VitestBenchReport {
+    fn into_results(self) -> BenchResults {
+        self.files
+            .into_iter()
+            .flat_map(|file| file.groups)
+            .flat_map(|group| {
+                group.benchmarks.into_iter().map(move |bench| {
+                    let summary = Summary {
+                        mean: bench.mean,
+                        sd: bench.sd,
+                        samples: bench.sample_count.unwrap_or(bench.samples.len()),
+                    };
+                    (format!("{} > {}", group.full_name, bench.name), summary)
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Verdict {
+    Regressed,
+    Improved,
+    NoChange,
+}
+
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub name: String,
+
+    /// Relative change of the mean time from the baseline, e.g. `0.1` for 10% slower.
+    pub change: f64,
+    pub p_value: Option<f64>,
+    pub verdict: Verdict,
+}
+
+/// Compares each benchmark in `current` against the same benchmark in `baseline`, if any.
+pub fn compare(baseline: &BenchResults, current: &BenchResults) -> Vec<Comparison> {
+    current
+        .iter()
+        .filter_map(|(name, current)| {
+            let baseline = baseline.get(name)?;
+            let change = current.mean / baseline.mean - 1.;
+            let p_value = stats::welch_t_test(baseline, current);
+            let significant = p_value.is_some_and(|p| p < SIGNIFICANCE_LEVEL);
+            let verdict = if !significant || change.abs() < NOISE_THRESHOLD {
+                Verdict::NoChange
+            } else if change > 0. {
+                Verdict::Regressed
+            } else {
+                Verdict::Improved
+            };
+            Some(Comparison {
+                name: name.clone(),
+                change,
+                p_value,
+                verdict,
+            })
+        })
+        .collect()
+}
+
+/// Kept outside of `node_modules` so results survive a reinstall of dependencies.
+fn bench_dir(ws: &Workspace) -> PathBuf {
+    ws.root.join(".depot").join("bench")
+}
+
+/// Where the results of every run are stored, keyed by the commit that was checked out.
+fn commit_dir(ws: &Workspace, commit: &str) -> PathBuf {
+    bench_dir(ws).join("commits").join(commit)
+}
+
+fn baseline_dir(ws: &Workspace, name: &str) -> PathBuf {
+    bench_dir(ws).join("baselines").join(name)
+}
+
+fn results_path(dir: &Path, pkg: &PackageName) -> PathBuf {
+    dir.join(format!("{pkg}.json"))
+}
+
+fn save_results(dir: &Path, pkg: &PackageName, results: &BenchResults) -> Result<()> {
+    let path = results_path(dir, pkg);
+    utils::create_dir_if_missing(path.parent().unwrap())?;
+    utils::write(path, serde_json::to_string_pretty(results)?)
+}
+
+fn load_results(dir: &Path, pkg: &PackageName) -> Result<Option<BenchResults>> {
+    let path = results_path(dir, pkg);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(&path)?;
+    let results = serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid benchmark results: {}", path.display()))?;
+    Ok(Some(results))
+}
+
+/// A saved baseline takes precedence over a git revision of the same name.
+fn resolve_baseline(ws: &Workspace, baseline: &str) -> Result<PathBuf> {
+    let dir = baseline_dir(ws, baseline);
+    if dir.exists() {
+        return Ok(dir);
+    }
+    let commit = utils::get_git_commit(&ws.root, baseline).ok_or_else(|| {
+        anyhow!(
+            "Unknown baseline `{baseline}`, which is neither a saved baseline nor a git revision"
+        )
+    })?;
+    Ok(commit_dir(ws, &commit))
+}
+
+fn has_benches(pkg: &Package) -> Result<bool> {
+    Ok(pkg.root.join("bench").exists()
+        || !pkg
+            .glob_files(&["**/*.bench.{ts,tsx}".to_string()])?
+            .is_empty())
+}
+
+/// The results of benchmarking one package, compared against the baseline if one was given.
+#[derive(Debug)]
+struct PackageBench {
+    name: PackageName,
+    results: BenchResults,
+    comparisons: Option<Vec<Comparison>>,
+}
+
+#[derive(Debug)]
+pub struct BenchCommand {
+    args: BenchArgs,
+    benches: Mutex<Vec<PackageBench>>,
+
+    /// Held while vitest runs, since concurrent benchmarks would skew each other's timings.
+    running: tokio::sync::Mutex<()>,
+}
+
+impl CoreCommand for BenchCommand {
+    fn name(&self) -> String {
+        "bench".into()
+    }
+
+    fn summary(&self) -> Option<String> {
+        let mut benches = self.benches.lock().unwrap();
+        if benches.is_empty() {
+            return None;
+        }
+        benches.sort_by(|a, b| a.name.cmp(&b.name));
+        Some(format_summary(&benches, self.args.baseline.as_deref()))
+    }
+
+    fn finish(&self, _ws: &Workspace) -> Result<()> {
+        if !self.args.fail_on_regression {
+            return Ok(());
+        }
+        let benches = self.benches.lock().unwrap();
+        let regressions = count_regressions(&benches);
+        ensure!(regressions == 0, "{regressions} benchmark(s) regressed");
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl PackageCommand for BenchCommand {
+    async fn run_pkg(&self, pkg: &Package) -> Result<()> {
+        if !has_benches(pkg)? {
+            return Ok(());
+        }
+
+        let ws = pkg.workspace();
+        let baseline = match &self.args.baseline {
+            Some(baseline) => Some(resolve_baseline(ws, baseline)?),
+            None => None,
+        };
+
+        let vitest_args = match &self.args.vitest_args {
+            Some(vitest_args) => {
+                Some(shlex::split(vitest_args).context("Failed to parse vitest args")?)
+            }
+            None => None,
+        };
+
+        let output_path = pkg
+            .root
+            .join("node_modules")
+            .join(".depot")
+            .join("vitest-bench.json");
+        utils::create_dir_if_missing(output_path.parent().unwrap())?;
+
+        {
+            let _running = self.running.lock().await;
+            pkg.exec("vitest", |cmd| {
+                cmd.args(["bench", "--run"]);
+                cmd.arg(format!("--outputJson={}", output_path.display()));
+                if let Some(vitest_args) = vitest_args {
+                    cmd.args(vitest_args);
+                }
+            })
+            .await?;
+        }
+
+        let contents = fs::read_to_string(&output_path)
+            .with_context(|| format!("Missing benchmark results: {}", output_path.display()))?;
+        let report: VitestBenchReport = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse vitest bench results for {}", pkg.name))?;
+        let results = report.into_results();
+
+        // Loaded before saving, in case the baseline being compared against is also being replaced.
+        let comparisons = match &baseline {
+            Some(dir) => load_results(dir, &pkg.name)?.map(|baseline| compare(&baseline, &results)),
+            None => None,
+        };
+
+        if let Some(commit) = utils::get_git_commit(&ws.root, "HEAD") {
+            save_results(&commit_dir(ws, &commit), &pkg.name, &results)?;
+        }
+        if let Some(name) = &self.args.save_baseline {
+            save_results(&baseline_dir(ws, name), &pkg.name, &results)?;
+        }
+
+        self.benches.lock().unwrap().push(PackageBench {
+            name: pkg.name.clone(),
+            results,
+            comparisons,
+        });
+
+        Ok(())
+    }
+
+    fn uses_dev_deps(&self) -> bool {
+        true
+    }
+
+    /// Benchmarks measure the machine as much as the code, so they always run.
+    fn input_files(&self, _pkg: &Package) -> Option<Vec<PathBuf>> {
+        None
+    }
+
+    fn deps(&self) -> Vec<Command> {
+        vec![BuildCommand::new(BuildArgs::default()).kind()]
+    }
+}
+
+impl BenchCommand {
+    pub fn new(args: BenchArgs) -> Self {
+        BenchCommand {
+            args,
+            benches: Mutex::new(Vec::new()),
+            running: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn kind(self) -> Command {
+        Command::package(self)
+    }
+}
+
+/// Formats a duration in milliseconds with a unit that suits its magnitude.
+fn format_time(ms: f64) -> String {
+    if ms >= 1000. {
+        format!("{:.2}s", ms / 1000.)
+    } else if ms >= 1. {
+        format!("{ms:.2}ms")
+    } else if ms >= 1e-3 {
+        format!("{:.2}µs", ms * 1e3)
+    } else {
+        format!("{:.2}ns", ms * 1e6)
+    }
+}
+
+fn count_regressions(benches: &[PackageBench]) -> usize {
+    benches
+        .iter()
+        .flat_map(|bench| bench.comparisons.iter().flatten())
+        .filter(|c| c.verdict == Verdict::Regressed)
+        .count()
+}
+
+fn format_summary(benches: &[PackageBench], baseline: Option<&str>) -> String {
+    let mut out = match baseline {
+        Some(baseline) => format!("Benchmark results (compared to `{baseline}`):\n"),
+        None => String::from("Benchmark results:\n"),
+    };
+    for bench in benches {
+        writeln!(out, "  {}", bench.name).unwrap();
+        let width = bench.results.keys().map(String::len).max().unwrap_or(0);
+        let comparisons = bench.comparisons.as_ref().map(|comparisons| {
+            comparisons
+                .iter()
+                .map(|c| (c.name.as_str(), c))
+                .collect::<BTreeMap<_, _>>()
+        });
+        for (name, summary) in &bench.results {
+            write!(out, "    {name:<width$}  {:>9}", format_time(summary.mean)).unwrap();
+            match comparisons.as_ref().map(|c| c.get(name.as_str())) {
+                Some(Some(comparison)) => {
+                    let verdict = match comparison.verdict {
+                        Verdict::Regressed => "regressed",
+                        Verdict::Improved => "improved",
+                        Verdict::NoChange => "no change",
+                    };
+                    write!(out, "  {:>+7.1}%  {verdict}", comparison.change * 100.).unwrap();
+                    if let Some(p) = comparison.p_value {
+                        write!(out, " (p = {p:.3})").unwrap();
+                    }
+                }
+                Some(None) => out.push_str("  (not in baseline)"),
+                None => {}
+            }
+            out.push('\n');
+        }
+        if baseline.is_some() && bench.comparisons.is_none() {
+            out.push_str("    (no baseline results for this package)\n");
+        }
+    }
+
+    let regressions = count_regressions(benches);
+    if regressions > 0 {
+        writeln!(out, "\n{regressions} benchmark(s) regressed").unwrap();
+    }
+
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn summary(mean: f64) -> Summary {
+        Summary {
+            mean,
+            sd: 0.01,
+            samples: 1000,
+        }
+    }
+
+    #[test]
+    fn test_into_results() {
+        let report: VitestBenchReport = serde_json::from_str(
+            r#"{
+              "files": [{
+                "filepath": "/ws/packages/foo/bench/sort.bench.ts",
+                "groups": [{
+                  "fullName": "bench/sort.bench.ts > sort",
+                  "benchmarks": [
+                    { "name": "quick", "mean": 0.5, "sd": 0.01, "sampleCount": 200, "hz": 2000 },
+                    { "name": "bubble", "mean": 2.5, "sd": 0.2, "samples": [2.4, 2.6] }
+                  ]
+                }]
+              }]
+            }"#,
+        )
+        .unwrap();
+        let results = report.into_results();
+        assert_eq!(results["bench/sort.bench.ts > sort > quick"].samples, 200);
+        assert_eq!(results["bench/sort.bench.ts > sort > bubble"].samples, 2);
+    }
+
+    #[test]
+    fn test_compare() {
+        let baseline = BenchResults::from([
+            ("slower".into(), summary(1.)),
+            ("faster".into(), summary(1.)),
+            ("noise".into(), summary(1.)),
+        ]);
+        let current = BenchResults::from([
+            ("slower".into(), summary(1.2)),
+            ("faster".into(), summary(0.8)),
+            ("noise".into(), summary(1.001)),
+            ("new".into(), summary(1.)),
+        ]);
+        let verdicts = compare(&baseline, &current)
+            .into_iter()
+            .map(|c| (c.name, c.verdict))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            verdicts,
+            BTreeMap::from([
+                ("faster".into(), Verdict::Improved),
+                ("noise".into(), Verdict::NoChange),
+                ("slower".into(), Verdict::Regressed),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(1500.), "1.50s");
+        assert_eq!(format_time(2.), "2.00ms");
+        assert_eq!(format_time(0.0025), "2.50µs");
+        assert_eq!(format_time(0.000_004), "4.00ns");
+    }
+}
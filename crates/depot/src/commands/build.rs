@@ -1,16 +1,30 @@
-use std::{fs, path::Path, time::Duration};
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
 
-use anyhow::{Result, anyhow, ensure};
+use anyhow::{Result, ensure};
 use futures::{FutureExt, future::try_join_all};
-use log::debug;
+use log::{debug, warn};
 use notify::RecursiveMode;
+use sha2::{Digest, Sha256};
 
-use super::init::{InitArgs, InitCommand};
+use super::{
+    artifacts::ArtifactManifest,
+    cjs,
+    init::{InitArgs, InitCommand},
+    project_refs, size,
+};
 use crate::{
     utils,
     workspace::{
         Command, CommandRuntime, CoreCommand, PackageCommand,
-        package::{Package, Target},
+        package::{LibFormat, Package, PackageName, Target},
+        profile::DEV_PROFILE,
     },
 };
 
@@ -18,7 +32,7 @@ use crate::{
 #[derive(clap::Parser, Default, Debug)]
 #[allow(clippy::struct_excessive_bools)]
 pub struct BuildArgs {
-    /// Build in release mode
+    /// Build in release mode, an alias for `--profile release`
     #[arg(short, long)]
     pub release: bool,
 
@@ -38,19 +52,42 @@ pub struct BuildArgs {
 #[derive(Debug)]
 pub struct BuildCommand {
     args: BuildArgs,
+    size_reports: Mutex<Vec<(PackageName, String)>>,
+    shared_tsc: project_refs::SharedBuild,
 }
 
 const BUILD_SCRIPT: &str = "build.mjs";
 
+/// How long to wait for a library to re-emit its outputs after a source change before
+/// assuming that nothing will be emitted (e.g. due to a type error).
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(10);
+
 impl CoreCommand for BuildCommand {
     fn name(&self) -> String {
         "build".into()
     }
+
+    fn summary(&self) -> Option<String> {
+        let mut reports = self.size_reports.lock().unwrap().clone();
+        if reports.is_empty() {
+            return None;
+        }
+        reports.sort();
+        Some(
+            reports
+                .into_iter()
+                .map(|(_, report)| report)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
 }
 
 #[async_trait::async_trait]
 impl PackageCommand for BuildCommand {
     async fn run_pkg(&self, pkg: &Package) -> Result<()> {
+        ArtifactManifest::invalidate(pkg)?;
+
         if pkg.root.join(BUILD_SCRIPT).exists() {
             self.build_script(pkg).await?;
         }
@@ -59,16 +96,78 @@ impl PackageCommand for BuildCommand {
 
         match pkg.target {
             Target::Script | Target::Site => processes.push(self.vite(pkg).boxed()),
-            Target::Lib => processes.push(self.copy_assets(pkg).boxed()),
+            Target::Lib => {
+                cjs::sync_exports(pkg)?;
+                processes.push(self.copy_assets(pkg).boxed());
+                if pkg.lib_formats().contains(&LibFormat::Cjs) {
+                    processes.push(self.tsc_cjs(pkg).boxed());
+                }
+                if self.args.watch {
+                    processes.push(Self::watch_outputs(pkg).boxed());
+                }
+            }
         }
 
         processes.extend([self.tsc(pkg).boxed(), self.biome(pkg).boxed()]);
 
         try_join_all(processes).await?;
 
+        if !self.args.watch {
+            let manifest = ArtifactManifest::generate(pkg)?;
+            manifest.save(pkg)?;
+            if !pkg.target.is_lib() {
+                self.report_sizes(pkg, &manifest).await?;
+            }
+        }
+
         Ok(())
     }
 
+    /// Libraries that depend on `pkg` only see its declarations and which assets it exports.
+    fn interface_hash(&self, pkg: &Package) -> Option<String> {
+        if !pkg.target.is_lib() {
+            return None;
+        }
+
+        let mut declarations = pkg
+            .glob_files(&["dist/**/*.d.{ts,cts,mts}".to_string()])
+            .ok()?;
+        declarations.sort();
+        let mut assets = pkg.asset_files().collect::<Vec<_>>();
+        assets.sort();
+
+        // The hash is persisted across runs, so it needs a stable digest. Each field is
+        // prefixed by its length to keep the boundaries between files unambiguous.
+        let mut hasher = Sha256::new();
+        let mut update = |bytes: &[u8]| {
+            hasher.update((bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        };
+        for path in declarations {
+            update(path.strip_prefix(&pkg.root).ok()?.to_str()?.as_bytes());
+            update(&fs::read(&path).ok()?);
+        }
+        for path in assets {
+            update(path.strip_prefix(&pkg.root).ok()?.to_str()?.as_bytes());
+        }
+        Some(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Sites and scripts bundle the code of their dependencies, and build scripts may use anything.
+    fn uses_dep_interfaces(&self, pkg: &Package) -> bool {
+        pkg.target.is_lib() && !pkg.root.join(BUILD_SCRIPT).exists()
+    }
+
+    fn pkg_key(&self, pkg: &Package) -> String {
+        // Outputs of one profile don't make the build for another profile fresh.
+        let profile = &pkg.workspace().profile.name;
+        if profile == DEV_PROFILE {
+            format!("build-{}", pkg.name)
+        } else {
+            format!("build:{profile}-{}", pkg.name)
+        }
+    }
+
     fn deps(&self) -> Vec<Command> {
         vec![InitCommand::new(InitArgs::default()).kind()]
     }
@@ -82,9 +181,27 @@ impl PackageCommand for BuildCommand {
     }
 }
 
+/// Vite has no CLI option for `define`, so profile defines only apply if the package's vite
+/// config spreads `DEPOT_DEFINE` into its own, as configs generated by `depot new` do.
+fn reads_depot_define(pkg: &Package) -> bool {
+    [
+        "vite.config.ts",
+        "vite.config.mts",
+        "vite.config.js",
+        "vite.config.mjs",
+    ]
+    .iter()
+    .filter_map(|file| fs::read_to_string(pkg.root.join(file)).ok())
+    .any(|contents| contents.contains("DEPOT_DEFINE"))
+}
+
 impl BuildCommand {
     pub fn new(args: BuildArgs) -> Self {
-        BuildCommand { args }
+        BuildCommand {
+            args,
+            size_reports: Mutex::new(Vec::new()),
+            shared_tsc: project_refs::SharedBuild::default(),
+        }
     }
 
     pub fn kind(self) -> Command {
@@ -92,74 +209,329 @@ impl BuildCommand {
     }
 
     async fn tsc(&self, pkg: &Package) -> Result<()> {
-        pkg.exec("tsc", |cmd| {
+        if project_refs::enabled(pkg.workspace()) {
+            return self.shared_tsc.check(pkg, self.args.watch).await;
+        }
+
+        let profile = &pkg.workspace().profile;
+        let incremental_args = Self::tsc_incremental_args(pkg, "esm", &pkg.root.join("dist"))?;
+        let configure = |cmd: &mut tokio::process::Command| {
+            cmd.envs(&profile.env);
             cmd.arg("--pretty");
+            cmd.args(&incremental_args);
             if self.args.watch {
                 cmd.arg("--watch");
             }
-            if pkg.target.is_lib() && !self.args.release {
+            if pkg.target.is_lib() && profile.sourcemap {
                 cmd.arg("--sourceMap");
             }
-        })
-        .await
+            if pkg.target.is_lib() && !pkg.lib_formats().contains(&LibFormat::Esm) {
+                cmd.arg("--noEmit");
+            }
+        };
+
+        // `tsc --watch` doesn't notice when the declarations of a dependency are rewritten,
+        // so restart it to re-check against them.
+        if self.args.watch {
+            pkg.exec_restarting("tsc", configure).await
+        } else {
+            pkg.exec("tsc", configure).await
+        }
+    }
+
+    /// Returns the arguments that make tsc reuse the build info of its previous run with `--incremental`.
+    ///
+    /// Build info is kept per profile under `node_modules/.depot/tsbuildinfo`, named by `kind`.
+    /// tsc doesn't check that the files it emitted still exist, so the build info is discarded
+    /// if `outputs` is missing. If `outputs` was last emitted by another profile, that profile's
+    /// build info describes them, so it is copied over: tsc then sees that the options changed
+    /// and re-emits the files without checking them again.
+    fn tsc_incremental_args(pkg: &Package, kind: &str, outputs: &Path) -> Result<Vec<OsString>> {
+        let ws = pkg.workspace();
+        let dir = pkg
+            .root
+            .join("node_modules")
+            .join(".depot")
+            .join("tsbuildinfo");
+        let build_info = dir
+            .join(&ws.profile.name)
+            .join(format!("{kind}.tsbuildinfo"));
+        let marker = dir.join(format!("{kind}.profile"));
+        let emitted_by = fs::read_to_string(&marker).ok();
+        // Non-incremental builds emit into `outputs` too, so they still record their profile.
+        utils::create_dir_if_missing(&dir)?;
+        utils::write(&marker, &ws.profile.name)?;
+        if !ws.common.incremental {
+            return Ok(Vec::new());
+        }
+
+        utils::create_dir_if_missing(build_info.parent().unwrap())?;
+        if !outputs.exists() {
+            debug!(
+                "Discarding build info for {}: {}",
+                pkg.name,
+                build_info.display()
+            );
+            if build_info.exists() {
+                fs::remove_file(&build_info)?;
+            }
+        } else if let Some(emitted_by) = emitted_by
+            && emitted_by != ws.profile.name
+        {
+            let emitted_info = dir.join(&emitted_by).join(format!("{kind}.tsbuildinfo"));
+            if emitted_info.exists() {
+                debug!(
+                    "Reusing build info of profile {emitted_by} for {}",
+                    pkg.name
+                );
+                fs::copy(&emitted_info, &build_info)?;
+            } else if build_info.exists() {
+                fs::remove_file(&build_info)?;
+            }
+        }
+
+        Ok(vec![
+            "--incremental".into(),
+            "--tsBuildInfoFile".into(),
+            build_info.into_os_string(),
+        ])
+    }
+
+    /// Emits a CJS build of a library into a staging directory, then converts it into `dist/`.
+    async fn tsc_cjs(&self, pkg: &Package) -> Result<()> {
+        let profile = &pkg.workspace().profile;
+        let staging_dir = cjs::staging_dir(pkg);
+        let incremental_args = Self::tsc_incremental_args(pkg, "cjs", &staging_dir)?;
+        if incremental_args.is_empty() {
+            utils::remove_dir_all_if_exists(&staging_dir)?;
+        }
+        utils::create_dir_if_missing(&staging_dir)?;
+
+        let configure = |cmd: &mut tokio::process::Command| {
+            cmd.envs(&profile.env);
+            cmd.args([
+                "--pretty",
+                "--module",
+                "commonjs",
+                "--moduleResolution",
+                "node10",
+            ]);
+            cmd.arg("--outDir").arg(&staging_dir);
+            cmd.args(&incremental_args);
+            if self.args.watch {
+                cmd.arg("--watch");
+            }
+            if profile.sourcemap {
+                cmd.arg("--sourceMap");
+            }
+        };
+
+        if !self.args.watch {
+            pkg.exec("tsc", configure).await?;
+            return cjs::convert_all(pkg);
+        }
+        let tsc = pkg.exec_restarting("tsc", configure);
+
+        let convert = async {
+            let mut watcher = utils::FileWatcher::new(Duration::from_millis(200))?;
+            watcher.watch(&staging_dir, RecursiveMode::Recursive)?;
+            watcher.watch(&pkg.root.join("src"), RecursiveMode::Recursive)?;
+            // tsc rewrites and deletes staging files as it goes, so a failure here is logged
+            // rather than ending the watch.
+            while let Some(paths) = watcher.next().await {
+                if let Err(e) = cjs::remove_stale(pkg) {
+                    warn!("Failed to remove stale CommonJS outputs: {e:?}");
+                }
+                for path in paths {
+                    if let Err(e) = cjs::convert_file(pkg, &path) {
+                        warn!("Failed to convert {} to CommonJS: {e:?}", path.display());
+                    }
+                }
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+        futures::future::try_join(tsc, convert).await?;
+        Ok(())
     }
 
     async fn biome(&self, pkg: &Package) -> Result<()> {
-        let process = pkg.start_process("biome", |cmd| {
+        let biome_args = |cmd: &mut tokio::process::Command, files: Vec<PathBuf>| {
             cmd.arg("check");
-            cmd.args(pkg.source_files());
+            cmd.args(files);
             cmd.arg("--colors=force");
-            // TODO: watch mode
-        })?;
+        };
 
+        let mut process =
+            pkg.start_process("biome", |cmd| biome_args(cmd, pkg.source_files().collect()))?;
         let status = process.wait().await?;
-        ensure!(!self.args.lint_fail || status.success(), "biome failed");
+
+        if !self.args.watch {
+            ensure!(!self.args.lint_fail || status.success(), "biome failed");
+            return Ok(());
+        }
+
+        let mut watcher = utils::FileWatcher::new(Duration::from_secs(1))?;
+        for dir in ["src", "tests"] {
+            let dir = pkg.root.join(dir);
+            if dir.exists() {
+                watcher.watch(&dir, RecursiveMode::Recursive)?;
+            }
+        }
+        // Picks up changes to config files like vite.config.ts in the package root.
+        watcher.watch(&pkg.root, RecursiveMode::NonRecursive)?;
+
+        let canonical = |path: PathBuf| path.canonicalize().ok();
+        while let Some(paths) = watcher.next().await {
+            let source_files = pkg
+                .source_files()
+                .filter_map(canonical)
+                .collect::<HashSet<_>>();
+            let changed = paths
+                .into_iter()
+                .filter_map(canonical)
+                .filter(|path| source_files.contains(path))
+                .collect::<HashSet<_>>();
+            if changed.is_empty() {
+                continue;
+            }
+
+            // Only re-check the changed files, replacing the previous results in the biome pane.
+            process = pkg.replace_process(&process, "biome", |cmd| {
+                biome_args(cmd, changed.into_iter().collect());
+            })?;
+            process.wait().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Tracks when a library's outputs are being rebuilt so that dependents in watch mode
+    /// only pick them up once they've settled.
+    async fn watch_outputs(pkg: &Package) -> Result<()> {
+        let dist_dir = pkg.root.join("dist");
+        utils::create_dir_if_missing(&dist_dir)?;
+
+        let mut src_watcher = utils::FileWatcher::new(Duration::from_millis(100))?;
+        src_watcher.watch(&pkg.root.join("src"), RecursiveMode::Recursive)?;
+        let mut dist_watcher = utils::FileWatcher::new(Duration::from_secs(1))?;
+        dist_watcher.watch(&dist_dir, RecursiveMode::Recursive)?;
+
+        // The first build is underway, and dependents wait for it to settle before starting.
+        pkg.mark_outputs_stale();
+        let timeout = tokio::time::sleep(OUTPUT_TIMEOUT);
+        tokio::pin!(timeout);
+        let mut stale = true;
+        loop {
+            tokio::select! {
+              Some(_) = src_watcher.next() => {
+                pkg.mark_outputs_stale();
+                stale = true;
+                timeout.as_mut().reset(tokio::time::Instant::now() + OUTPUT_TIMEOUT);
+              }
+              Some(_) = dist_watcher.next() => {
+                pkg.mark_outputs_fresh(true);
+                stale = false;
+              }
+              () = &mut timeout, if stale => {
+                pkg.mark_outputs_fresh(false);
+                stale = false;
+              }
+              else => break,
+            }
+        }
 
         Ok(())
     }
 
     async fn vite(&self, pkg: &Package) -> Result<()> {
-        pkg.exec("vite", |cmd| {
-            cmd.env("FORCE_COLOR", "1");
-            let no_server = pkg.manifest.config.no_server.unwrap_or(false);
-            if pkg.target.is_site() && self.args.watch && !no_server {
-                cmd.arg("dev");
-            } else {
-                cmd.arg("build");
-                if self.args.watch {
-                    cmd.arg("--watch");
-                }
-                if self.args.release {
-                    // cmd.env("NODE_ENV", "production");
-                } else {
-                    cmd.args([
-                        "--sourcemap",
-                        "true",
-                        "--minify",
-                        "false",
-                        "--mode",
-                        "development",
-                    ]);
-
-                    // TODO: running `NODE_ENV=development vite build` seems to
-                    // break Vike.
-
-                    // cmd.env("NODE_ENV", "development");
-                }
+        let profile = &pkg.workspace().profile;
+        if !profile.define.is_empty() && !reads_depot_define(pkg) {
+            warn!(
+                "{}: the `{}` profile sets `define`, but the package's vite config doesn't read \
+                 DEPOT_DEFINE, so it has no effect",
+                pkg.name, profile.name
+            );
+        }
+        let define = profile.define_json()?;
+        if !self.args.watch || self.is_dev_server(pkg) {
+            return pkg
+                .exec("vite", |cmd| self.vite_args(pkg, &define, cmd))
+                .await;
+        }
+
+        // `vite build --watch` can pick up a dependency's outputs while they're half-written,
+        // so restart it once they've settled.
+        pkg.exec_restarting("vite", |cmd| self.vite_args(pkg, &define, cmd))
+            .await
+    }
+
+    fn is_dev_server(&self, pkg: &Package) -> bool {
+        let no_server = pkg.manifest.config.no_server.unwrap_or(false);
+        pkg.target.is_site() && self.args.watch && !no_server
+    }
+
+    fn vite_args(&self, pkg: &Package, define: &str, cmd: &mut tokio::process::Command) {
+        let profile = &pkg.workspace().profile;
+        cmd.env("FORCE_COLOR", "1");
+        cmd.envs(&profile.env);
+        cmd.env("DEPOT_DEFINE", define);
+        if self.is_dev_server(pkg) {
+            cmd.arg("dev");
+        } else {
+            cmd.arg("build");
+            if self.args.watch {
+                cmd.arg("--watch");
             }
-        })
-        .await
+            if !profile.minify {
+                cmd.args(["--minify", "false"]);
+            }
+            if profile.sourcemap {
+                cmd.args(["--sourcemap", "true"]);
+            }
+        }
+
+        // TODO: NODE_ENV is left to the profile's `env`, since running
+        // `NODE_ENV=development vite build` seems to break Vike.
+        cmd.args(["--mode", &profile.mode]);
+    }
+
+    /// Records the sizes of the package's outputs and checks them against its budgets.
+    async fn report_sizes(&self, pkg: &Package, manifest: &ArtifactManifest) -> Result<()> {
+        let report = size::measure(pkg, manifest).await?;
+        let previous = size::load_previous(pkg);
+        let table = size::format_table(pkg, &report, previous.as_ref());
+        self.size_reports
+            .lock()
+            .unwrap()
+            .push((pkg.name.clone(), table));
+
+        // Only a build within budget becomes the baseline, so a failing build keeps
+        // reporting its change against the last good one.
+        if let Some(budgets) = &pkg.manifest.config.size_limit {
+            let violations = size::check_budgets(&report, budgets)?;
+            ensure!(
+                violations.is_empty(),
+                "Bundle size budgets exceeded for {}:\n  {}",
+                pkg.name,
+                violations.join("\n  ")
+            );
+        }
+
+        size::save(pkg, &report)
     }
 
     async fn build_script(&self, pkg: &Package) -> Result<()> {
+        let profile = &pkg.workspace().profile;
         pkg.exec("pnpm", |cmd| {
+            cmd.envs(&profile.env);
             cmd.args(["exec", "node", BUILD_SCRIPT]);
             if self.args.watch {
                 cmd.arg("--watch");
             }
-            if self.args.release {
+            if profile.release {
                 cmd.arg("--release");
             }
+            cmd.args(["--profile", &profile.name]);
         })
         .await
     }
@@ -182,23 +554,49 @@ impl BuildCommand {
         }
 
         if self.args.watch {
-            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
-            let timeout = Duration::from_secs(1);
-            let mut debouncer =
-                notify_debouncer_mini::new_debouncer(timeout, None, move |events| {
-                    let _ = tx.send(events);
-                })?;
-
-            for file in pkg.asset_files() {
-                debouncer
-                    .watcher()
-                    .watch(&file, RecursiveMode::NonRecursive)?;
-            }
-
-            while let Some(events) = rx.recv().await {
-                let events = events.map_err(|e| anyhow!("File watch errors: {e:?}"))?;
-                for event in events {
-                    copy(&event.path)?;
+            let mut watcher = utils::FileWatcher::new(Duration::from_secs(1))?;
+            watcher.watch(&src_dir, RecursiveMode::Recursive)?;
+
+            // Event paths may be canonicalized by the OS (e.g. /private/var on macOS).
+            let canonical_src_dir = src_dir.canonicalize()?;
+            let to_src_path = |path: &Path| -> Option<PathBuf> {
+                let rel_path = path
+                    .strip_prefix(&src_dir)
+                    .or_else(|_| path.strip_prefix(&canonical_src_dir))
+                    .ok()?;
+                Some(src_dir.join(rel_path))
+            };
+
+            let sync = |path: &Path| -> Result<()> {
+                let target_path = dst_dir.join(path.strip_prefix(&src_dir)?);
+                if path.is_dir() {
+                    // Directories moved into `src` only generate an event for the directory itself.
+                    for file in pkg.asset_files().filter(|file| file.starts_with(path)) {
+                        copy(&file)?;
+                    }
+                } else if path.exists() {
+                    if pkg.is_asset_file(path) {
+                        copy(path)?;
+                    }
+                } else if pkg.is_asset_file(path) && target_path.exists() {
+                    debug!("removing: {}", target_path.display());
+                    if target_path.is_dir() {
+                        fs::remove_dir_all(&target_path)?;
+                    } else {
+                        fs::remove_file(&target_path)?;
+                    }
+                }
+                Ok(())
+            };
+
+            while let Some(paths) = watcher.next().await {
+                for path in paths {
+                    let Some(path) = to_src_path(&path) else {
+                        continue;
+                    };
+                    if let Err(e) = sync(&path) {
+                        warn!("Failed to sync asset {}: {e:?}", path.display());
+                    }
                 }
             }
         }
@@ -0,0 +1,309 @@
+//! CJS output for library packages.
+//!
+//! tsc can only emit `.js` files, which Node treats as ESM in `"type": "module"` packages. So the
+//! CJS build is emitted into a staging directory, then renamed to `.cjs`/`.d.cts` and copied
+//! next to the ESM output in `dist/`. Sharing `dist/` means both formats resolve the same assets.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use anyhow::{Context, Result, bail};
+use log::info;
+use regex::{Captures, Regex};
+use serde_json::{Value, json};
+
+use crate::{
+    utils,
+    workspace::package::{LibFormat, Package},
+};
+
+pub fn staging_dir(pkg: &Package) -> PathBuf {
+    pkg.root.join("node_modules").join(".depot").join("cjs")
+}
+
+/// Extensions of the files emitted by tsc, along with their CJS counterparts.
+const EMITTED_EXTENSIONS: [(&str, &str); 4] = [
+    (".d.ts.map", ".d.cts.map"),
+    (".d.ts", ".d.cts"),
+    (".js.map", ".cjs.map"),
+    (".js", ".cjs"),
+];
+
+/// Maps a file emitted by tsc to its CJS name, or `None` if it isn't a module or declaration.
+fn cjs_path(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let renamed = EMITTED_EXTENSIONS
+        .into_iter()
+        .find_map(|(ext, cjs_ext)| Some(format!("{}{cjs_ext}", name.strip_suffix(ext)?)))?;
+    Some(path.with_file_name(renamed))
+}
+
+/// Maps an emitted file to the module it was emitted for, e.g. `a/lib.d.cts` to `a/lib`.
+fn module_path(path: &Path, cjs: bool) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let module = EMITTED_EXTENSIONS
+        .into_iter()
+        .find_map(|(ext, cjs_ext)| name.strip_suffix(if cjs { cjs_ext } else { ext }))?;
+    Some(path.with_file_name(module))
+}
+
+/// Rewrites relative module specifiers like `"./util"` or `"./util.js"` to point to `"./util.cjs"`,
+/// along with source map references. Specifiers of other files like `"./style.css"` are left as is.
+fn rewrite_specifiers(contents: &str) -> String {
+    static SPECIFIER: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r#"(require\(|from |import\()(["'])(\.{1,2}/(?:[^"'/]*/)*[^"'./]*)(\.js)?(["'])"#,
+        )
+        .unwrap()
+    });
+    static SOURCE_MAP: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(sourceMappingURL=.*?)\.(js|d\.ts)\.map").unwrap());
+
+    let contents = SPECIFIER.replace_all(contents, |caps: &Captures| {
+        format!("{}{}{}.cjs{}", &caps[1], &caps[2], &caps[3], &caps[5])
+    });
+    SOURCE_MAP
+        .replace_all(&contents, |caps: &Captures| {
+            let ext = if &caps[2] == "js" { "cjs" } else { "d.cts" };
+            format!("{}.{ext}.map", &caps[1])
+        })
+        .into_owned()
+}
+
+/// Converts a file emitted into the staging directory and writes it to `dist/`.
+pub fn convert_file(pkg: &Package, path: &Path) -> Result<()> {
+    let staging_dir = staging_dir(pkg);
+    let Ok(rel_path) = path.strip_prefix(&staging_dir) else {
+        return Ok(());
+    };
+    let Some(cjs_rel_path) = cjs_path(rel_path) else {
+        return Ok(());
+    };
+    if !path.is_file() {
+        return Ok(());
+    }
+
+    let contents = fs::read_to_string(path)?;
+    let target_path = pkg.root.join("dist").join(cjs_rel_path);
+    utils::create_dir_if_missing(target_path.parent().unwrap())?;
+    utils::write(target_path, rewrite_specifiers(&contents))
+}
+
+fn files_in(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    ignore::WalkBuilder::new(dir)
+        .standard_filters(false)
+        .build()
+        .filter_map(|entry| Some(entry.ok()?.into_path()))
+        .filter(|path| path.is_file())
+}
+
+pub fn convert_all(pkg: &Package) -> Result<()> {
+    remove_stale(pkg)?;
+    for file in files_in(&staging_dir(pkg)) {
+        convert_file(pkg, &file)?;
+    }
+    Ok(())
+}
+
+/// Removes CJS outputs, both in `dist/` and in the staging directory, whose source file
+/// no longer exists. tsc never deletes the outputs of a deleted source.
+pub fn remove_stale(pkg: &Package) -> Result<()> {
+    let src_dir = pkg.root.join("src");
+    let modules = files_in(&src_dir)
+        .filter_map(|path| Some(path.strip_prefix(&src_dir).ok()?.with_extension("")))
+        .collect::<HashSet<_>>();
+    for (dir, cjs) in [(pkg.root.join("dist"), true), (staging_dir(pkg), false)] {
+        for file in files_in(&dir) {
+            let Some(module) = file
+                .strip_prefix(&dir)
+                .ok()
+                .and_then(|rel_path| module_path(rel_path, cjs))
+            else {
+                continue;
+            };
+            if !modules.contains(&module) {
+                fs::remove_file(&file).with_context(|| {
+                    format!("Failed to remove stale output: {}", file.display())
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the `exports` map matching the package's entry points and formats.
+fn expected_exports(pkg: &Package) -> Value {
+    let formats = pkg.lib_formats();
+    let src_dir = pkg.root.join("src");
+    let exports = pkg
+        .entry_points()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| {
+            let rel_path = entry.strip_prefix(&src_dir).ok()?.with_extension("");
+            let stem = rel_path.to_str()?.replace('\\', "/");
+            let subpath = if i == 0 {
+                ".".to_string()
+            } else {
+                format!("./{stem}")
+            };
+            let mut conditions = serde_json::Map::new();
+            if formats.contains(&LibFormat::Esm) {
+                conditions.insert(
+                    "import".into(),
+                    json!({"types": format!("./dist/{stem}.d.ts"), "default": format!("./dist/{stem}.js")}),
+                );
+            }
+            if formats.contains(&LibFormat::Cjs) {
+                conditions.insert(
+                    "require".into(),
+                    json!({"types": format!("./dist/{stem}.d.cts"), "default": format!("./dist/{stem}.cjs")}),
+                );
+            }
+            Some((subpath, Value::Object(conditions)))
+        })
+        .collect::<serde_json::Map<_, _>>();
+    Value::Object(exports)
+}
+
+fn string_leaves(value: &Value) -> Vec<&str> {
+    match value {
+        Value::String(s) => vec![s.as_str()],
+        Value::Object(obj) => obj.values().flat_map(string_leaves).collect(),
+        Value::Array(arr) => arr.iter().flat_map(string_leaves).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns true if `exports` maps `subpath` to `target`, either directly or through a `*` pattern.
+fn exports_target(exports: &Value, subpath: &str, target: &str) -> bool {
+    let Value::Object(exports) = exports else {
+        return subpath == "." && string_leaves(exports).contains(&target);
+    };
+    exports.iter().any(|(key, value)| {
+        let leaves = string_leaves(value);
+        if key == subpath {
+            return leaves.contains(&target);
+        }
+        let Some((prefix, suffix)) = key.split_once('*') else {
+            return false;
+        };
+        let Some(matched) = subpath
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(suffix))
+        else {
+            return false;
+        };
+        leaves
+            .iter()
+            .any(|leaf| leaf.replace('*', matched) == target)
+    })
+}
+
+/// Adds `exports` to the package's `package.json`, keeping the order of its other keys.
+fn write_exports(pkg: &Package, exports: Value) -> Result<()> {
+    let path = pkg.root.join("package.json");
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read manifest: {}", path.display()))?;
+    let mut manifest: Value = serde_json::from_str(&contents)?;
+    let Value::Object(fields) = &mut manifest else {
+        bail!("Manifest is not an object: {}", path.display());
+    };
+    fields.insert("exports".into(), exports);
+    info!("Generated \"exports\" for {}", pkg.name);
+    utils::write(path, serde_json::to_string_pretty(&manifest)?)
+}
+
+/// Checks that the package's `exports` expose every entry point in every configured format.
+/// If the package has no `exports`, they are generated instead.
+pub fn sync_exports(pkg: &Package) -> Result<()> {
+    let config = &pkg.manifest.config;
+    if config.formats.is_none() && config.entry_points.is_none() {
+        return Ok(());
+    }
+
+    let expected = expected_exports(pkg);
+    let Some(exports) = &pkg.manifest.manifest.exports else {
+        return write_exports(pkg, expected);
+    };
+    let actual = serde_json::to_value(exports)?;
+
+    let missing = expected
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(subpath, conditions)| {
+            string_leaves(conditions)
+                .into_iter()
+                .filter(|target| !target.contains(".d."))
+                .filter(|target| !exports_target(&actual, subpath, target))
+                .map(move |target| format!("{subpath} -> {target}"))
+        })
+        .collect::<Vec<_>>();
+
+    if !missing.is_empty() {
+        bail!(
+            "The \"exports\" of {} are missing:\n  {}\nConsider using:\n\"exports\": {}",
+            pkg.name,
+            missing.join("\n  "),
+            serde_json::to_string_pretty(&expected)?
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cjs_path() {
+        let cjs = |s: &str| cjs_path(Path::new(s)).map(|p| p.display().to_string());
+        assert_eq!(cjs("a/lib.js").as_deref(), Some("a/lib.cjs"));
+        assert_eq!(cjs("lib.d.ts").as_deref(), Some("lib.d.cts"));
+        assert_eq!(cjs("lib.js.map").as_deref(), Some("lib.cjs.map"));
+        assert_eq!(cjs("style.css"), None);
+    }
+
+    #[test]
+    fn test_rewrite_specifiers() {
+        let src = r#"const a = require("./a");
+const b = require("../b.js");
+const c = require("react");
+import { d } from './d.js';
+require("./style.css");
+const data = require("../nested/data.json");
+const e = require("./nested/e.mjs");
+//# sourceMappingURL=lib.js.map"#;
+        let expected = r#"const a = require("./a.cjs");
+const b = require("../b.cjs");
+const c = require("react");
+import { d } from './d.cjs';
+require("./style.css");
+const data = require("../nested/data.json");
+const e = require("./nested/e.mjs");
+//# sourceMappingURL=lib.cjs.map"#;
+        assert_eq!(rewrite_specifiers(src), expected);
+    }
+
+    #[test]
+    fn test_exports_target() {
+        let exports = json!({
+          ".": { "import": "./dist/lib.js", "require": "./dist/lib.cjs" },
+          "./*": { "default": "./dist/*.js" }
+        });
+        assert!(exports_target(&exports, ".", "./dist/lib.cjs"));
+        assert!(exports_target(&exports, "./cli", "./dist/cli.js"));
+        assert!(!exports_target(&exports, "./cli", "./dist/cli.cjs"));
+        assert!(exports_target(
+            &json!("./dist/lib.js"),
+            ".",
+            "./dist/lib.js"
+        ));
+    }
+}
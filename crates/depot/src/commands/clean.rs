@@ -1,5 +1,6 @@
 use anyhow::Result;
 
+use super::artifacts;
 use crate::{
     utils,
     workspace::{Command, CoreCommand, Workspace, WorkspaceCommand},
@@ -36,7 +37,8 @@ impl WorkspaceCommand for CleanCommand {
     async fn run_ws(&self, ws: &Workspace) -> Result<()> {
         let mut to_delete = vec![ws.root.join("node_modules")];
         for pkg in &ws.packages {
-            to_delete.extend([pkg.root.join("node_modules"), pkg.root.join("dist")]);
+            artifacts::remove(pkg)?;
+            to_delete.push(pkg.root.join("node_modules"));
         }
 
         for dir in to_delete {
@@ -0,0 +1,450 @@
+//! Merging the istanbul coverage data written by vitest's v8 provider for each package,
+//! and rendering it as workspace-wide lcov, HTML and text reports.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, ensure};
+
+use crate::{
+    utils,
+    workspace::package::{CoverageThresholds, Package, PackageName},
+};
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct Position {
+    pub line: u32,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct Range {
+    pub start: Position,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct FunctionMapping {
+    pub name: String,
+    pub line: u32,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct BranchMapping {
+    pub line: u32,
+}
+
+/// Coverage of one file in istanbul's `coverage-final.json` format.
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FileCoverage {
+    /// Path to the file, made relative to the workspace root by [`load`].
+    pub path: String,
+    pub statement_map: BTreeMap<String, Range>,
+    pub fn_map: BTreeMap<String, FunctionMapping>,
+    pub branch_map: BTreeMap<String, BranchMapping>,
+    pub s: BTreeMap<String, u64>,
+    pub f: BTreeMap<String, u64>,
+    pub b: BTreeMap<String, Vec<u64>>,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Metric {
+    pub covered: usize,
+    pub total: usize,
+}
+
+impl Metric {
+    fn new<'a>(counts: impl IntoIterator<Item = &'a u64>) -> Self {
+        let mut metric = Metric::default();
+        for count in counts {
+            metric.total += 1;
+            if *count > 0 {
+                metric.covered += 1;
+            }
+        }
+        metric
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn percent(self) -> f64 {
+        if self.total == 0 {
+            100.
+        } else {
+            self.covered as f64 / self.total as f64 * 100.
+        }
+    }
+
+    fn add(&mut self, other: Metric) {
+        self.covered += other.covered;
+        self.total += other.total;
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Summary {
+    pub statements: Metric,
+    pub branches: Metric,
+    pub functions: Metric,
+    pub lines: Metric,
+}
+
+impl Summary {
+    fn add(&mut self, other: Summary) {
+        self.statements.add(other.statements);
+        self.branches.add(other.branches);
+        self.functions.add(other.functions);
+        self.lines.add(other.lines);
+    }
+
+    fn metrics(&self) -> [(&'static str, Metric); 4] {
+        [
+            ("statements", self.statements),
+            ("branches", self.branches),
+            ("functions", self.functions),
+            ("lines", self.lines),
+        ]
+    }
+}
+
+impl FileCoverage {
+    /// Hit counts of each line, taking the most executed statement that starts on the line.
+    fn line_hits(&self) -> BTreeMap<u32, u64> {
+        let mut lines = BTreeMap::new();
+        for (id, range) in &self.statement_map {
+            let count = self.s.get(id).copied().unwrap_or(0);
+            let hits = lines.entry(range.start.line).or_insert(0);
+            *hits = count.max(*hits);
+        }
+        lines
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary {
+            statements: Metric::new(self.s.values()),
+            branches: Metric::new(self.b.values().flatten()),
+            functions: Metric::new(self.f.values()),
+            lines: Metric::new(self.line_hits().values()),
+        }
+    }
+}
+
+pub fn summarize<'a>(files: impl IntoIterator<Item = &'a FileCoverage>) -> Summary {
+    let mut summary = Summary::default();
+    for file in files {
+        summary.add(file.summary());
+    }
+    summary
+}
+
+/// The vitest plugin that collects coverage, which older workspaces may not have installed.
+const PROVIDER: &str = "@vitest/coverage-v8";
+
+/// Checks that the coverage provider can be resolved from `pkg`, so a missing install fails
+/// with instructions rather than vitest's prompt to install it.
+pub fn check_provider(pkg: &Package) -> Result<()> {
+    let ws = pkg.workspace();
+    let installed = [&pkg.root, &ws.root]
+        .iter()
+        .any(|root| root.join("node_modules").join(PROVIDER).exists());
+    let workspace_flag = if ws.monorepo { " -w" } else { "" };
+    ensure!(
+        installed,
+        "Coverage requires `{PROVIDER}`, which is not installed. Install it with:\n  pnpm add -D{workspace_flag} {PROVIDER}"
+    );
+    Ok(())
+}
+
+/// Where vitest writes the coverage data for `pkg`.
+pub fn reports_dir(pkg: &Package) -> PathBuf {
+    pkg.root
+        .join("node_modules")
+        .join(".depot")
+        .join("coverage")
+}
+
+/// Loads the coverage data that vitest wrote for `pkg`, with paths relative to `ws_root`.
+pub fn load(pkg: &Package, ws_root: &Path) -> Result<Vec<FileCoverage>> {
+    let path = reports_dir(pkg).join("coverage-final.json");
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read coverage data: {}", path.display()))?;
+    let files: BTreeMap<String, FileCoverage> = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse coverage data: {}", path.display()))?;
+    Ok(files
+        .into_values()
+        .map(|mut file| {
+            let rel_path = Path::new(&file.path)
+                .strip_prefix(ws_root)
+                .unwrap_or(Path::new(&file.path));
+            file.path = rel_path.display().to_string().replace('\\', "/");
+            file
+        })
+        .collect())
+}
+
+/// Returns a message for each metric in `summary` below its threshold.
+pub fn check_thresholds(summary: &Summary, thresholds: &CoverageThresholds) -> Vec<String> {
+    let limits = [
+        thresholds.statements,
+        thresholds.branches,
+        thresholds.functions,
+        thresholds.lines,
+    ];
+    summary
+        .metrics()
+        .into_iter()
+        .zip(limits)
+        .filter_map(|((name, metric), limit)| {
+            let limit = limit?;
+            (metric.percent() < limit).then(|| {
+                format!(
+                    "{name} coverage of {:.2}% is below the threshold of {limit}%",
+                    metric.percent()
+                )
+            })
+        })
+        .collect()
+}
+
+fn format_lcov(files: &[&FileCoverage]) -> String {
+    let mut out = String::new();
+    for file in files {
+        writeln!(out, "TN:\nSF:{}", file.path).unwrap();
+
+        for (id, func) in &file.fn_map {
+            writeln!(out, "FN:{},{}", func.line, func.name).unwrap();
+            let count = file.f.get(id).copied().unwrap_or(0);
+            writeln!(out, "FNDA:{count},{}", func.name).unwrap();
+        }
+        let functions = Metric::new(file.f.values());
+        writeln!(out, "FNF:{}\nFNH:{}", functions.total, functions.covered).unwrap();
+
+        let lines = file.line_hits();
+        for (line, hits) in &lines {
+            writeln!(out, "DA:{line},{hits}").unwrap();
+        }
+        let lines = Metric::new(lines.values());
+        writeln!(out, "LF:{}\nLH:{}", lines.total, lines.covered).unwrap();
+
+        for (block, (id, branch)) in file.branch_map.iter().enumerate() {
+            let counts = file.b.get(id).map(Vec::as_slice).unwrap_or_default();
+            for (i, count) in counts.iter().enumerate() {
+                writeln!(out, "BRDA:{},{block},{i},{count}", branch.line).unwrap();
+            }
+        }
+        let branches = Metric::new(file.b.values().flatten());
+        writeln!(out, "BRF:{}\nBRH:{}", branches.total, branches.covered).unwrap();
+
+        out.push_str("end_of_record\n");
+    }
+    out
+}
+
+fn format_html(files: &[&FileCoverage], total: &Summary) -> String {
+    let cells = |summary: &Summary| {
+        summary
+            .metrics()
+            .iter()
+            .map(|(_, metric)| {
+                format!(
+                    "<td>{:.2}% ({}/{})</td>",
+                    metric.percent(),
+                    metric.covered,
+                    metric.total
+                )
+            })
+            .collect::<String>()
+    };
+
+    let mut rows = String::new();
+    for file in files {
+        writeln!(
+            rows,
+            "<tr><td>{}</td>{}</tr>",
+            file.path
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;"),
+            cells(&file.summary())
+        )
+        .unwrap();
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Coverage report</title>
+<style>
+body {{ font-family: sans-serif; }}
+table {{ border-collapse: collapse; }}
+td, th {{ padding: 4px 12px; border-bottom: 1px solid #ddd; text-align: right; }}
+td:first-child, th:first-child {{ text-align: left; }}
+</style>
+</head>
+<body>
+<h1>Coverage report</h1>
+<table>
+<tr><th>File</th><th>Statements</th><th>Branches</th><th>Functions</th><th>Lines</th></tr>
+{rows}<tr><th>Total</th>{}</tr>
+</table>
+</body>
+</html>
+"#,
+        cells(total)
+    )
+}
+
+/// Writes `lcov.info` and `index.html` for the files of every package to `coverage/` in `ws_root`.
+pub fn write_reports(ws_root: &Path, packages: &[(PackageName, Vec<FileCoverage>)]) -> Result<()> {
+    let mut files = packages
+        .iter()
+        .flat_map(|(_, files)| files)
+        .collect::<Vec<_>>();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let total = summarize(files.iter().copied());
+
+    let dir = ws_root.join("coverage");
+    utils::create_dir_if_missing(&dir)?;
+    utils::write(dir.join("lcov.info"), format_lcov(&files))?;
+    utils::write(dir.join("index.html"), format_html(&files, &total))?;
+    Ok(())
+}
+
+/// Renders the coverage of each package and the whole workspace as a table.
+pub fn format_table(packages: &[(PackageName, Vec<FileCoverage>)]) -> String {
+    let mut rows = packages
+        .iter()
+        .map(|(name, files)| (name.to_string(), summarize(files)))
+        .collect::<Vec<_>>();
+    if packages.len() > 1 {
+        let total = summarize(packages.iter().flat_map(|(_, files)| files));
+        rows.push(("total".to_string(), total));
+    }
+
+    let width = rows
+        .iter()
+        .map(|(name, _)| name.len())
+        .chain(["package".len()])
+        .max()
+        .unwrap();
+    let mut out = String::from("Coverage:\n");
+    writeln!(
+        out,
+        "  {:<width$}  statements    branches   functions       lines",
+        "package"
+    )
+    .unwrap();
+    for (name, summary) in rows {
+        let percents = summary
+            .metrics()
+            .iter()
+            .map(|(_, metric)| format!("{:>10.2}%", metric.percent()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(out, "  {name:<width$} {percents}").unwrap();
+    }
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn file() -> FileCoverage {
+        serde_json::from_value(serde_json::json!({
+          "path": "packages/foo/src/lib.ts",
+          "statementMap": {
+            "0": { "start": { "line": 1, "column": 0 }, "end": { "line": 1, "column": 10 } },
+            "1": { "start": { "line": 2, "column": 0 }, "end": { "line": 2, "column": 10 } },
+            "2": { "start": { "line": 2, "column": 12 }, "end": { "line": 2, "column": 20 } },
+            "3": { "start": { "line": 4, "column": 0 }, "end": { "line": 4, "column": 10 } }
+          },
+          "fnMap": {
+            "0": { "name": "add", "line": 1, "decl": {}, "loc": {} },
+            "1": { "name": "sub", "line": 4, "decl": {}, "loc": {} }
+          },
+          "branchMap": {
+            "0": { "line": 2, "type": "if", "locations": [] }
+          },
+          "s": { "0": 3, "1": 0, "2": 2, "3": 0 },
+          "f": { "0": 3, "1": 0 },
+          "b": { "0": [2, 0] }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_summary() {
+        let summary = file().summary();
+        assert_eq!(
+            summary.statements,
+            Metric {
+                covered: 2,
+                total: 4
+            }
+        );
+        assert_eq!(
+            summary.branches,
+            Metric {
+                covered: 1,
+                total: 2
+            }
+        );
+        assert_eq!(
+            summary.functions,
+            Metric {
+                covered: 1,
+                total: 2
+            }
+        );
+        assert_eq!(
+            summary.lines,
+            Metric {
+                covered: 2,
+                total: 3
+            }
+        );
+
+        let thresholds = CoverageThresholds {
+            lines: Some(60.),
+            functions: Some(80.),
+            ..Default::default()
+        };
+        let violations = check_thresholds(&summary, &thresholds);
+        assert_eq!(
+            violations,
+            ["functions coverage of 50.00% is below the threshold of 80%"]
+        );
+    }
+
+    #[test]
+    fn test_lcov() {
+        let file = file();
+        assert_eq!(
+            format_lcov(&[&file]),
+            "TN:
+SF:packages/foo/src/lib.ts
+FN:1,add
+FNDA:3,add
+FN:4,sub
+FNDA:0,sub
+FNF:2
+FNH:1
+DA:1,3
+DA:2,2
+DA:4,0
+LF:3
+LH:2
+BRDA:2,0,0,2
+BRDA:2,0,1,0
+BRF:2
+BRH:1
+end_of_record
+"
+        );
+    }
+}
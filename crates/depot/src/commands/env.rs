@@ -0,0 +1,45 @@
+use anyhow::{Context, Result};
+
+use crate::workspace::Workspace;
+
+/// Print the variables loaded from `.env` files for the selected profile,
+/// including the package's own files if one is selected with `-p`
+#[derive(clap::Parser, Debug)]
+pub struct EnvArgs {}
+
+pub struct EnvCommand {
+    #[allow(unused)]
+    args: EnvArgs,
+}
+
+impl EnvCommand {
+    pub fn new(args: EnvArgs) -> Self {
+        EnvCommand { args }
+    }
+
+    #[allow(clippy::unused_self)]
+    pub fn run(self, ws: &Workspace) -> Result<()> {
+        let vars = match &ws.common.package {
+            Some(name) => ws
+                .packages
+                .iter()
+                .find(|pkg| &pkg.name == name)
+                .with_context(|| format!("Could not find package with name: {name}"))?
+                .env()?,
+            None => ws.env()?,
+        };
+
+        for (key, value) in vars {
+            let needs_quotes = value
+                .chars()
+                .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '#'));
+            if needs_quotes {
+                println!("{key}={value:?}");
+            } else {
+                println!("{key}={value}");
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use std::{collections::HashMap, fmt::Write};
+
+use anyhow::Result;
+
+use super::{
+    Command,
+    build::{BuildArgs, BuildCommand},
+};
+use crate::workspace::{
+    PackageCommand, Workspace,
+    dep_graph::{DepEdge, DepGraph},
+    package::{Package, Platform, Target},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug)]
+pub enum GraphFormat {
+    #[default]
+    Dot,
+    Mermaid,
+    Json,
+}
+
+/// Print the package graph or the task graph of a command
+#[derive(clap::Parser, Debug)]
+pub struct GraphArgs {
+    /// Print the task graph for a command (e.g. `test` or "build --release") instead of the package graph
+    #[arg(long, value_name = "COMMAND")]
+    pub tasks: Option<String>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = GraphFormat::Dot)]
+    pub format: GraphFormat,
+}
+
+pub struct GraphCommand {
+    args: GraphArgs,
+}
+
+#[derive(serde::Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum FingerprintStatus {
+    Fresh,
+    Stale,
+}
+
+#[derive(serde::Serialize)]
+struct GraphNode {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<Target>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform: Option<Platform>,
+    fingerprint: FingerprintStatus,
+}
+
+#[derive(serde::Serialize)]
+struct GraphEdge {
+    from: String,
+    to: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct GraphOutput {
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+}
+
+impl GraphNode {
+    fn for_package(pkg: &Package, fresh: bool) -> Self {
+        GraphNode {
+            id: pkg.name.to_string(),
+            command: None,
+            package: Some(pkg.name.to_string()),
+            target: Some(pkg.target),
+            platform: Some(pkg.platform),
+            fingerprint: FingerprintStatus::from_fresh(fresh),
+        }
+    }
+
+    fn annotations(&self) -> Vec<String> {
+        let mut annots = Vec::new();
+        if let Some(command) = &self.command {
+            annots.push(command.clone());
+        }
+        if let (Some(target), Some(platform)) = (self.target, self.platform) {
+            annots.push(format!("{target}, {platform}"));
+        }
+        annots.push(self.fingerprint.to_string());
+        annots
+    }
+}
+
+impl FingerprintStatus {
+    fn from_fresh(fresh: bool) -> Self {
+        if fresh {
+            FingerprintStatus::Fresh
+        } else {
+            FingerprintStatus::Stale
+        }
+    }
+}
+
+impl std::fmt::Display for FingerprintStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FingerprintStatus::Fresh => write!(f, "fresh"),
+            FingerprintStatus::Stale => write!(f, "stale"),
+        }
+    }
+}
+
+impl GraphOutput {
+    fn from_dep_graph<T: std::hash::Hash + Eq + Clone, E: DepEdge>(
+        graph: &DepGraph<T, E>,
+        node: impl Fn(&T) -> GraphNode,
+    ) -> Self {
+        let ids = graph
+            .nodes()
+            .map(|n| (n.clone(), node(n)))
+            .collect::<HashMap<_, _>>();
+
+        let mut edges = graph
+            .edges()
+            .map(|(from, to, edge)| GraphEdge {
+                from: ids[from].id.clone(),
+                to: ids[to].id.clone(),
+                kind: edge.describe(),
+            })
+            .collect::<Vec<_>>();
+        edges.sort_by(|e1, e2| (&e1.from, &e1.to).cmp(&(&e2.from, &e2.to)));
+
+        let mut nodes = ids.into_values().collect::<Vec<_>>();
+        nodes.sort_by(|n1, n2| n1.id.cmp(&n2.id));
+
+        GraphOutput { nodes, edges }
+    }
+
+    fn to_dot(&self, name: &str) -> String {
+        let mut out = format!("digraph {name} {{\n");
+        for node in &self.nodes {
+            let label = std::iter::once(node.id.clone())
+                .chain(node.annotations())
+                .collect::<Vec<_>>()
+                .join("\\n");
+            writeln!(out, "  {:?} [label=\"{label}\"];", node.id).unwrap();
+        }
+        for edge in &self.edges {
+            match &edge.kind {
+                Some(kind) => {
+                    writeln!(out, "  {:?} -> {:?} [label={kind:?}];", edge.from, edge.to).unwrap();
+                }
+                None => writeln!(out, "  {:?} -> {:?};", edge.from, edge.to).unwrap(),
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn to_mermaid(&self) -> String {
+        let index = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id.as_str(), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut out = String::from("graph TD\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let label = std::iter::once(node.id.clone())
+                .chain(node.annotations())
+                .collect::<Vec<_>>()
+                .join("<br/>");
+            writeln!(out, "  n{i}[\"{label}\"]").unwrap();
+        }
+        for edge in &self.edges {
+            let (from, to) = (index[&*edge.from], index[&*edge.to]);
+            match &edge.kind {
+                Some(kind) => writeln!(out, "  n{from} -->|{kind}| n{to}").unwrap(),
+                None => writeln!(out, "  n{from} --> n{to}").unwrap(),
+            }
+        }
+        out
+    }
+}
+
+impl GraphCommand {
+    pub fn new(args: GraphArgs) -> Self {
+        GraphCommand { args }
+    }
+
+    fn package_graph(ws: &Workspace) -> GraphOutput {
+        let build = BuildCommand::new(BuildArgs::default());
+        GraphOutput::from_dep_graph(&ws.pkg_graph, |pkg| {
+            let fresh = ws.is_fresh(&build.pkg_key(pkg), pkg.all_files());
+            GraphNode::for_package(pkg, fresh)
+        })
+    }
+
+    fn task_graph(ws: &Workspace, command: &str) -> Result<GraphOutput> {
+        let root = Command::parse_str(command)?.kind(ws)?;
+        let task_graph = ws.task_graph(&root);
+        Ok(GraphOutput::from_dep_graph(&task_graph, |task| {
+            let fresh = task.is_fresh();
+            let mut node = match task.package() {
+                Some(pkg) => GraphNode::for_package(pkg, fresh),
+                None => GraphNode {
+                    id: String::new(),
+                    command: None,
+                    package: None,
+                    target: None,
+                    platform: None,
+                    fingerprint: FingerprintStatus::from_fresh(fresh),
+                },
+            };
+            node.id = task.key().to_string();
+            node.command = Some(task.command().name());
+            node
+        }))
+    }
+
+    pub fn run(self, ws: &Workspace) -> Result<()> {
+        let (output, name) = match &self.args.tasks {
+            Some(command) => (Self::task_graph(ws, command)?, "tasks"),
+            None => (Self::package_graph(ws), "packages"),
+        };
+
+        let rendered = match self.args.format {
+            GraphFormat::Dot => output.to_dot(name),
+            GraphFormat::Mermaid => output.to_mermaid(),
+            GraphFormat::Json => serde_json::to_string_pretty(&output)? + "\n",
+        };
+        print!("{rendered}");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! Merges the vitest results of every package into a `JUnit` XML report.
+
+use std::{fmt::Write, path::Path};
+
+use super::test_report::{FileResult, PackageReport, TestStatus};
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Control characters other than whitespace aren't allowed in XML 1.0.
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn seconds(ms: Option<f64>) -> String {
+    format!("{:.3}", ms.unwrap_or(0.) / 1000.)
+}
+
+fn rel_path(file: &FileResult, ws_root: &Path) -> String {
+    let path = file.name.strip_prefix(ws_root).unwrap_or(&file.name);
+    path.display().to_string().replace('\\', "/")
+}
+
+fn write_failure(out: &mut String, message: &str) {
+    let summary = message.lines().next().unwrap_or_default();
+    writeln!(
+        out,
+        "      <failure message=\"{}\">{}</failure>",
+        escape(summary),
+        escape(message)
+    )
+    .unwrap();
+}
+
+fn write_suite(out: &mut String, result: &PackageReport, ws_root: &Path) {
+    let PackageReport { name, report, .. } = result;
+    let counts = report.counts();
+    let time = report.duration();
+    writeln!(
+        out,
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{}\">",
+        escape(&name.to_string()),
+        counts.passed + counts.failed + counts.skipped,
+        counts.failed,
+        counts.skipped,
+        seconds(Some(time))
+    )
+    .unwrap();
+
+    for file in &report.test_results {
+        let path = escape(&rel_path(file, ws_root));
+        let classname = escape(&format!("{name} {}", rel_path(file, ws_root)));
+
+        if file.status == TestStatus::Failed && file.assertion_results.is_empty() {
+            writeln!(
+                out,
+                "    <testcase classname=\"{classname}\" name=\"{path}\" file=\"{path}\" time=\"{}\">",
+                seconds(file.duration())
+            )
+            .unwrap();
+            write_failure(out, &file.message);
+            out.push_str("    </testcase>\n");
+        }
+
+        for test in &file.assertion_results {
+            let open = format!(
+                "    <testcase classname=\"{classname}\" name=\"{}\" file=\"{path}\" time=\"{}\"",
+                escape(&test.full_name),
+                seconds(test.duration)
+            );
+            match test.status {
+                TestStatus::Passed => writeln!(out, "{open}/>").unwrap(),
+                TestStatus::Skipped => {
+                    writeln!(out, "{open}>\n      <skipped/>\n    </testcase>").unwrap();
+                }
+                TestStatus::Failed => {
+                    writeln!(out, "{open}>").unwrap();
+                    write_failure(out, &test.failure_messages.join("\n"));
+                    out.push_str("    </testcase>\n");
+                }
+            }
+        }
+    }
+
+    out.push_str("  </testsuite>\n");
+}
+
+/// Renders one `<testsuite>` per package, with test files relative to `ws_root`.
+pub fn format_junit(results: &[PackageReport], ws_root: &Path) -> String {
+    let (mut tests, mut failures, mut skipped) = (0, 0, 0);
+    for result in results {
+        let counts = result.report.counts();
+        tests += counts.passed + counts.failed + counts.skipped;
+        failures += counts.failed;
+        skipped += counts.skipped;
+    }
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        out,
+        "<testsuites name=\"depot\" tests=\"{tests}\" failures=\"{failures}\" skipped=\"{skipped}\">"
+    )
+    .unwrap();
+    for result in results {
+        write_suite(&mut out, result, ws_root);
+    }
+    out.push_str("</testsuites>\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{commands::test_report::VitestReport, workspace::package::PackageName};
+
+    #[test]
+    fn test_format_junit() {
+        let report: VitestReport = serde_json::from_str(
+            r#"{
+              "testResults": [{
+                "name": "/ws/packages/foo/tests/add.test.ts",
+                "status": "failed",
+                "startTime": 1000,
+                "endTime": 1250,
+                "assertionResults": [
+                  { "fullName": "add works", "status": "passed", "duration": 2 },
+                  { "fullName": "add <big>", "status": "failed", "duration": 3,
+                    "failureMessages": ["expected 3 to be 100\n    at add.test.ts:5:20"] },
+                  { "fullName": "add later", "status": "skipped" }
+                ]
+              }]
+            }"#,
+        )
+        .unwrap();
+        let name = PackageName {
+            name: "foo".into(),
+            scope: None,
+        };
+        let result = PackageReport {
+            name,
+            root: "/ws/packages/foo".into(),
+            report,
+        };
+        let xml = format_junit(&[result], Path::new("/ws"));
+        assert_eq!(
+            xml,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="depot" tests="3" failures="1" skipped="1">
+  <testsuite name="foo" tests="3" failures="1" skipped="1" time="0.250">
+    <testcase classname="foo packages/foo/tests/add.test.ts" name="add works" file="packages/foo/tests/add.test.ts" time="0.002"/>
+    <testcase classname="foo packages/foo/tests/add.test.ts" name="add &lt;big&gt;" file="packages/foo/tests/add.test.ts" time="0.003">
+      <failure message="expected 3 to be 100">expected 3 to be 100
+    at add.test.ts:5:20</failure>
+    </testcase>
+    <testcase classname="foo packages/foo/tests/add.test.ts" name="add later" file="packages/foo/tests/add.test.ts" time="0.000">
+      <skipped/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, ensure};
+
+use crate::workspace::{
+    DEPOT_VERSION, Workspace,
+    package::{DependencyKind, Package, Platform, Target},
+};
+
+/// The latest version of the `depot metadata` output format.
+const FORMAT_VERSION: u32 = 1;
+
+/// Print machine-readable JSON describing the workspace
+#[derive(clap::Parser, Debug)]
+pub struct MetadataArgs {
+    /// Version of the output format to use
+    #[arg(long, default_value_t = FORMAT_VERSION)]
+    pub format_version: u32,
+}
+
+pub struct MetadataCommand {
+    args: MetadataArgs,
+}
+
+#[derive(serde::Serialize)]
+struct WorkspaceMetadata {
+    version: u32,
+    depot_version: &'static str,
+    workspace_root: PathBuf,
+    monorepo: bool,
+    packages: Vec<PackageMetadata>,
+}
+
+#[derive(serde::Serialize)]
+struct PackageMetadata {
+    name: String,
+    root: PathBuf,
+    manifest_path: PathBuf,
+    target: Target,
+    platform: Platform,
+    entry_points: Vec<PathBuf>,
+    dependencies: Vec<DependencyMetadata>,
+    source_files: Vec<PathBuf>,
+    asset_files: Vec<PathBuf>,
+}
+
+#[derive(serde::Serialize)]
+struct DependencyMetadata {
+    name: String,
+    kind: DependencyKind,
+    req: String,
+    workspace: bool,
+}
+
+impl PackageMetadata {
+    fn new(ws: &Workspace, pkg: &Package) -> Self {
+        let dependencies = pkg
+            .dependencies()
+            .map(|(name, kind, req)| DependencyMetadata {
+                workspace: ws.packages.iter().any(|other| other.name == name),
+                name: name.to_string(),
+                kind,
+                req: req.to_string(),
+            })
+            .collect();
+
+        let sorted = |files: Vec<PathBuf>| {
+            let mut files = files;
+            files.sort();
+            files
+        };
+
+        PackageMetadata {
+            name: pkg.name.to_string(),
+            root: pkg.root.clone(),
+            manifest_path: pkg.root.join("package.json"),
+            target: pkg.target,
+            platform: pkg.platform,
+            entry_points: pkg.entry_points(),
+            dependencies,
+            source_files: sorted(pkg.source_files().collect()),
+            asset_files: sorted(pkg.asset_files().collect()),
+        }
+    }
+}
+
+impl MetadataCommand {
+    pub fn new(args: MetadataArgs) -> Self {
+        MetadataCommand { args }
+    }
+
+    pub fn run(self, ws: &Workspace) -> Result<()> {
+        ensure!(
+            self.args.format_version == FORMAT_VERSION,
+            "Unsupported metadata format version: {} (latest is {FORMAT_VERSION})",
+            self.args.format_version
+        );
+
+        let mut packages = ws.packages.iter().collect::<Vec<_>>();
+        packages.sort_by(|p1, p2| p1.name.cmp(&p2.name));
+
+        let metadata = WorkspaceMetadata {
+            version: FORMAT_VERSION,
+            depot_version: DEPOT_VERSION,
+            workspace_root: ws.root.clone(),
+            monorepo: ws.monorepo,
+            packages: packages
+                .into_iter()
+                .map(|pkg| PackageMetadata::new(ws, pkg))
+                .collect(),
+        };
+
+        println!("{}", serde_json::to_string_pretty(&metadata)?);
+
+        Ok(())
+    }
+}
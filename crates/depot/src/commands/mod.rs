@@ -1,11 +1,37 @@
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+
+use self::{
+    bench::BenchCommand, build::BuildCommand, clean::CleanCommand, doc::DocCommand,
+    fix::FixCommand, fmt::FmtCommand, init::InitCommand, run::RunCommand, task::TaskCommand,
+    test::TestCommand,
+};
+use crate::workspace::{self, Workspace, profile::RELEASE_PROFILE};
+
+mod artifacts;
+pub mod bench;
 pub mod build;
+mod cjs;
 pub mod clean;
+mod coverage;
 pub mod doc;
+pub mod env;
 pub mod fix;
 pub mod fmt;
+pub mod graph;
 pub mod init;
+mod junit;
+pub mod metadata;
 pub mod new;
+mod project_refs;
+pub mod run;
+mod shard;
+mod size;
+mod stats;
+pub mod task;
 pub mod test;
+mod test_report;
+pub mod watch;
 
 #[derive(clap::Subcommand)]
 pub enum Command {
@@ -18,6 +44,8 @@ pub enum Command {
     #[clap(visible_alias = "t")]
     Test(test::TestArgs),
 
+    Bench(bench::BenchArgs),
+
     #[clap(visible_alias = "c")]
     Clean(clean::CleanArgs),
 
@@ -29,4 +57,69 @@ pub enum Command {
     Fix(fix::FixArgs),
 
     Init(init::InitArgs),
+
+    Graph(graph::GraphArgs),
+
+    Metadata(metadata::MetadataArgs),
+
+    Env(env::EnvArgs),
+
+    #[clap(visible_alias = "r")]
+    Run(run::RunArgs),
+
+    Task(task::TaskArgs),
+
+    #[clap(visible_alias = "w")]
+    Watch(watch::WatchArgs),
+}
+
+#[derive(clap::Parser)]
+#[command(no_binary_name = true)]
+struct CommandParser {
+    #[command(subcommand)]
+    command: Command,
+}
+
+impl Command {
+    /// Parses a command line like `"build --release"` into a [`Command`].
+    pub fn parse_str(s: &str) -> Result<Self> {
+        let args = shlex::split(s).with_context(|| format!("Failed to parse command: `{s}`"))?;
+        Self::parse_args(args)
+    }
+
+    /// Parses command line arguments like `["build", "--release"]` into a [`Command`].
+    pub fn parse_args(args: Vec<String>) -> Result<Self> {
+        let parser = CommandParser::try_parse_from(args)?;
+        Ok(parser.command)
+    }
+
+    /// Converts a parsed CLI command into a command that can be run on a workspace.
+    pub fn kind(self, ws: &Workspace) -> Result<workspace::Command> {
+        Ok(match self {
+            Command::Init(args) => InitCommand::new(args).kind(),
+            Command::Build(args) => {
+                // The profile is chosen for the whole workspace, so `--release` can't switch it
+                // for a nested command like `depot watch build --release`.
+                if args.release && ws.profile.name != RELEASE_PROFILE {
+                    bail!(
+                        "`build --release` can only be used as the top-level command, use `depot --profile release` instead"
+                    );
+                }
+                BuildCommand::new(args).kind()
+            }
+            Command::Test(args) => TestCommand::new(args).kind(),
+            Command::Bench(args) => BenchCommand::new(args).kind(),
+            Command::Fmt(args) => FmtCommand::new(args).kind(),
+            Command::Clean(args) => CleanCommand::new(args).kind(),
+            Command::Doc(args) => DocCommand::new(args).kind(),
+            Command::Fix(args) => FixCommand::new(args).kind(),
+            Command::Run(args) => RunCommand::new(args).kind(),
+            Command::Task(args) => TaskCommand::new(&args.name, ws)?.kind(),
+            Command::New(..) => bail!("`depot new` cannot be run on a workspace"),
+            Command::Graph(..) => bail!("`depot graph` cannot be run on a workspace"),
+            Command::Metadata(..) => bail!("`depot metadata` cannot be run on a workspace"),
+            Command::Env(..) => bail!("`depot env` cannot be run on a workspace"),
+            Command::Watch(..) => bail!("`depot watch` cannot be run on a workspace"),
+        })
+    }
 }
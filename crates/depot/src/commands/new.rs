@@ -409,10 +409,12 @@ minify: false,"#,
         }
 
         // This is needed for libraries like React that rely on process.env.NODE_ENV during bundling.
+        // DEPOT_DEFINE contains the `define` entries of the selected build profile.
         config.push((
             "define",
             r#"{
-  "process.env.NODE_ENV": JSON.stringify(mode)
+  "process.env.NODE_ENV": JSON.stringify(mode),
+  ...JSON.parse(process.env.DEPOT_DEFINE ?? "{}")
 }"#
             .into(),
         ));
@@ -562,6 +564,7 @@ export default defineConfig(({{ mode }}) => ({{
 
       // Testing
       "vitest",
+      "@vitest/coverage-v8",
 
       // Types
       "typescript",
@@ -629,6 +632,9 @@ export default defineConfig(({{ mode }}) => ({{
         };
         let ws_config = WorkspaceDepotConfig {
             depot_version: DEPOT_VERSION.to_string(),
+            profiles: IndexMap::new(),
+            project_references: false,
+            coverage_thresholds: None,
         };
         let mut config = serde_json::to_value(pkg_config)?;
         json_merge(&mut config, serde_json::to_value(ws_config)?);
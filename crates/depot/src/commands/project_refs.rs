@@ -0,0 +1,289 @@
+//! Workspace-wide type checking with `tsc --build` and project references.
+//!
+//! When "project-references" is enabled in the workspace's depot config, depot generates a
+//! tsconfig for each package under `node_modules/.depot` whose `references` follow the package
+//! graph, then checks every selected package with a single `tsc --build`. Its output is split back into the pane
+//! of the package that each diagnostic belongs to.
+
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use anyhow::{Result, ensure};
+use futures::{
+    FutureExt,
+    future::{BoxFuture, Shared},
+};
+use regex::Regex;
+use serde_json::{Map, Value, json};
+
+use crate::{
+    utils,
+    workspace::{
+        Workspace,
+        package::{LibFormat, Package, PackageName},
+        process::{LogLine, OutputHandler, Process},
+    },
+};
+
+const SCRIPT: &str = "tsc --build";
+
+pub fn enabled(ws: &Workspace) -> bool {
+    ws.monorepo && ws.config.project_references
+}
+
+/// Path of the tsconfig that depot generates for `pkg` and the selected profile.
+fn build_config_path(pkg: &Package) -> PathBuf {
+    pkg.root
+        .join("node_modules")
+        .join(".depot")
+        .join(format!("tsconfig.{}.json", pkg.workspace().profile.name))
+}
+
+/// Generates a tsconfig for every package in `pkgs` that has one, returning their paths.
+///
+/// Each generated tsconfig extends the package's own, references the generated tsconfigs of its
+/// dependencies, and sets the flags that a standalone `tsc` would get on its command line, since
+/// `tsc --build` doesn't accept them. The package's own tsconfig is never modified.
+fn write_build_configs(ws: &Workspace, pkgs: &[Package]) -> Result<Vec<PathBuf>> {
+    let has_tsconfig = |pkg: &Package| pkg.root.join("tsconfig.json").exists();
+    let mut paths = Vec::new();
+    for pkg in pkgs.iter().filter(|pkg| has_tsconfig(pkg)) {
+        let path = build_config_path(pkg);
+        let dir = path.parent().unwrap();
+
+        let references = ws
+            .pkg_graph
+            .immediate_deps_for(pkg)
+            .filter(|dep| dep.target.is_lib() && has_tsconfig(dep))
+            .map(|dep| json!({ "path": utils::relative_path(dir, &build_config_path(dep)) }))
+            .collect::<Vec<_>>();
+
+        let mut options = Map::new();
+        if pkg.target.is_lib() {
+            // tsc requires referenced projects to be composite.
+            options.insert("composite".into(), Value::Bool(true));
+            if ws.profile.sourcemap {
+                options.insert("sourceMap".into(), Value::Bool(true));
+            }
+            // Referenced projects can't disable emit, so libraries without an ESM build only
+            // emit the declarations that their dependents are checked against.
+            if !pkg.lib_formats().contains(&LibFormat::Esm) {
+                options.insert("emitDeclarationOnly".into(), Value::Bool(true));
+            }
+        } else {
+            options.insert("incremental".into(), Value::Bool(true));
+        }
+        options.insert(
+            "tsBuildInfoFile".into(),
+            Value::String(format!("tsbuildinfo/{}/build.tsbuildinfo", ws.profile.name)),
+        );
+
+        let config = json!({
+            "extends": utils::relative_path(dir, &pkg.root.join("tsconfig.json")),
+            "compilerOptions": options,
+            "references": references,
+        });
+        let contents = format!("{}\n", serde_json::to_string_pretty(&config)?);
+        // Rewriting an unchanged config would make `tsc --build --watch` rebuild.
+        if fs::read_to_string(&path).ok().as_deref() != Some(contents.as_str()) {
+            utils::create_dir_if_missing(dir)?;
+            utils::write(&path, contents)?;
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+fn strip_ansi(line: &str) -> std::borrow::Cow<'_, str> {
+    static ANSI: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap());
+    ANSI.replace_all(line, "")
+}
+
+/// Decides which package each line of `tsc --build` output belongs to.
+struct Demux {
+    /// Package directories relative to the workspace root, using `/` as the separator.
+    dirs: Vec<String>,
+    current: Option<usize>,
+}
+
+impl Demux {
+    fn new(ws_root: &Path, pkgs: &[Package]) -> Self {
+        let dirs = pkgs
+            .iter()
+            .map(|pkg| {
+                let rel_path = pkg.root.strip_prefix(ws_root).unwrap_or(&pkg.root);
+                rel_path.display().to_string().replace('\\', "/")
+            })
+            .collect();
+        Demux {
+            dirs,
+            current: None,
+        }
+    }
+
+    /// Returns the index of the package that `line` belongs to, or `None` if it concerns all packages.
+    ///
+    /// Diagnostics start with the path of a file. Indented lines, code frames and blank lines
+    /// continue the previous diagnostic.
+    fn route(&mut self, line: &str) -> Option<usize> {
+        let text = strip_ansi(line);
+        let text = text.trim_start_matches("\u{1b}c");
+        let continuation =
+            text.is_empty() || text.starts_with(|c: char| c.is_whitespace() || c.is_ascii_digit());
+        if !continuation {
+            self.current = self.dirs.iter().position(|dir| {
+                text.strip_prefix(dir.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+            });
+        }
+        self.current
+    }
+}
+
+/// Packages with type errors, or an error if `tsc --build` failed without reporting any.
+type BuildResult = Result<Arc<HashSet<PackageName>>, String>;
+
+async fn run(
+    ws: Workspace,
+    pkgs: Vec<Package>,
+    panes: Vec<Arc<Process>>,
+    watch: bool,
+) -> Result<Arc<HashSet<PackageName>>> {
+    let configs = write_build_configs(&ws, &pkgs)?;
+
+    let failed = Arc::new(Mutex::new(HashSet::new()));
+
+    let handler: OutputHandler = {
+        let demux = Mutex::new(Demux::new(&ws.root, &pkgs));
+        let panes = panes.clone();
+        let pkgs = pkgs.clone();
+        let failed = failed.clone();
+        Arc::new(
+            move |line: LogLine| match demux.lock().unwrap().route(&line.line) {
+                Some(i) => {
+                    if strip_ansi(&line.line).contains("error TS") {
+                        failed.lock().unwrap().insert(pkgs[i].name.clone());
+                    }
+                    panes[i].push_line(line);
+                }
+                None => {
+                    for pane in &panes {
+                        pane.push_line(line.clone());
+                    }
+                }
+            },
+        )
+    };
+
+    let mut cmd = ws.command("tsc")?;
+    cmd.envs(&ws.profile.env);
+    cmd.args(["--build", "--pretty"]);
+    if watch {
+        cmd.arg("--watch");
+    }
+    // Build info is always written for project references, so ignore it unless incremental.
+    if !ws.common.incremental {
+        cmd.arg("--force");
+    }
+    cmd.args(configs);
+
+    let process = Process::with_output_handler(SCRIPT.to_string(), cmd, handler)?;
+    let status = process.wait().await;
+    for pane in &panes {
+        pane.set_finished();
+    }
+
+    let failed = failed.lock().unwrap().clone();
+    ensure!(
+        status?.success() || !failed.is_empty(),
+        "Process `{SCRIPT}` failed"
+    );
+    Ok(Arc::new(failed))
+}
+
+struct Build {
+    future: Shared<BoxFuture<'static, BuildResult>>,
+    panes: Vec<Arc<Process>>,
+}
+
+/// A single `tsc --build` shared by the tasks of every package in a build.
+#[derive(Default)]
+pub struct SharedBuild(Mutex<Option<Build>>);
+
+impl SharedBuild {
+    /// Starts `tsc --build` if no package has started it yet, then waits for it to check `pkg`.
+    pub async fn check(&self, pkg: &Package, watch: bool) -> Result<()> {
+        let future = {
+            let mut build = self.0.lock().unwrap();
+            // A build whose panes were cleared belongs to a previous run, e.g. of `depot watch`.
+            let current = build.as_ref().is_some_and(|build| {
+                let processes = pkg.processes();
+                build
+                    .panes
+                    .iter()
+                    .any(|pane| processes.iter().any(|p| Arc::ptr_eq(p, pane)))
+            });
+            if !current {
+                *build = Some(Self::start(pkg.workspace(), watch));
+            }
+            build.as_ref().unwrap().future.clone()
+        };
+        let failed = future.await.map_err(anyhow::Error::msg)?;
+        ensure!(
+            !failed.contains(&pkg.name),
+            "Type checking `{}` failed",
+            pkg.name
+        );
+        Ok(())
+    }
+
+    fn start(ws: &Workspace, watch: bool) -> Build {
+        let pkgs = ws.pkg_graph.nodes().cloned().collect::<Vec<_>>();
+        let panes = pkgs
+            .iter()
+            .map(|pkg| {
+                let pane = Arc::new(Process::detached(SCRIPT.to_string()));
+                pkg.add_process(pane.clone());
+                pane
+            })
+            .collect::<Vec<_>>();
+        let future = run(ws.clone(), pkgs, panes.clone(), watch)
+            .map(|res| res.map_err(|err| format!("{err:?}")))
+            .boxed()
+            .shared();
+        Build { future, panes }
+    }
+}
+
+impl fmt::Debug for SharedBuild {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedBuild").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_demux_route() {
+        let mut demux = Demux {
+            dirs: vec!["packages/a".into(), "packages/ab".into()],
+            current: None,
+        };
+        let lines = [
+            "\u{1b}c[\u{1b}[90m12:00:00 PM\u{1b}[0m] Starting compilation in watch mode...",
+            "\u{1b}[96mpackages/ab/src/lib.ts\u{1b}[0m:1:7 - \u{1b}[91merror\u{1b}[0m TS2322: Type 'string' is not assignable to type 'number'.",
+            "",
+            "\u{1b}[7m1\u{1b}[0m const x: number = \"\";",
+            "packages/a/src/lib.ts(2,1): error TS1005: ';' expected.",
+            "Found 2 errors.",
+        ];
+        let routes = lines.map(|line| demux.route(line));
+        assert_eq!(routes, [None, Some(1), Some(1), Some(1), Some(0), None]);
+    }
+}
@@ -0,0 +1,99 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use anyhow::Result;
+
+use crate::workspace::{
+    Command, CommandRuntime, CoreCommand, PackageCommand,
+    package::{Package, PackageName},
+};
+
+/// Run a package.json script in every package that defines it
+#[derive(clap::Parser, Debug)]
+pub struct RunArgs {
+    /// Name of the script to run
+    pub script: String,
+
+    /// Run the script in all packages at once instead of in dependency order
+    #[arg(long, action)]
+    pub parallel: bool,
+
+    /// Additional arguments to pass to the script
+    #[arg(last = true)]
+    pub script_args: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct RunCommand {
+    args: RunArgs,
+    skipped: Mutex<Vec<PackageName>>,
+}
+
+impl RunCommand {
+    pub fn new(args: RunArgs) -> Self {
+        RunCommand {
+            args,
+            skipped: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn kind(self) -> Command {
+        Command::package(self)
+    }
+}
+
+impl CoreCommand for RunCommand {
+    fn name(&self) -> String {
+        "run".into()
+    }
+
+    fn summary(&self) -> Option<String> {
+        let mut skipped = self.skipped.lock().unwrap().clone();
+        if skipped.is_empty() {
+            return None;
+        }
+        skipped.sort();
+        let names = skipped
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        Some(format!(
+            "Skipped packages without a `{}` script: {names}",
+            self.args.script
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl PackageCommand for RunCommand {
+    async fn run_pkg(&self, pkg: &Package) -> Result<()> {
+        let has_script = pkg
+            .manifest
+            .manifest
+            .scripts
+            .as_ref()
+            .is_some_and(|scripts| scripts.contains_key(&self.args.script));
+        if !has_script {
+            self.skipped.lock().unwrap().push(pkg.name.clone());
+            return Ok(());
+        }
+
+        pkg.exec("pnpm", |cmd| {
+            cmd.args(["run", &self.args.script]);
+            cmd.args(&self.args.script_args);
+        })
+        .await
+    }
+
+    fn runtime(&self) -> CommandRuntime {
+        if self.args.parallel {
+            CommandRuntime::RunImmediately
+        } else {
+            CommandRuntime::WaitForDependencies
+        }
+    }
+
+    fn input_files(&self, _package: &Package) -> Option<Vec<PathBuf>> {
+        None
+    }
+}
@@ -0,0 +1,188 @@
+//! Partitioning tests across CI machines with `depot test --shard i/n`.
+//!
+//! Packages are assigned whole to the shard with the least work so far, weighted by how long
+//! their tests took in previous runs. A package that would take more than a shard's fair share
+//! is instead run on every shard, using vitest's own `--shard` to split its test files.
+//!
+//! Every machine must compute the same assignment, so the durations are read from a file that
+//! is shared between them, like one committed to the workspace, rather than from local history.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result, anyhow, ensure};
+
+use crate::{
+    utils,
+    workspace::{Workspace, package::PackageName},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Shard {
+    /// 1-based index of this shard.
+    pub index: usize,
+    pub count: usize,
+}
+
+impl FromStr for Shard {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Expected a shard like `2/4`, found `{s}`"))?;
+        let index = index.parse().context("Invalid shard index")?;
+        let count = count.parse().context("Invalid shard count")?;
+        ensure!(
+            (1..=count).contains(&index),
+            "Shard index must be between 1 and {count}"
+        );
+        Ok(Shard { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Assignment {
+    /// All of the package's tests run on the shard with this 1-based index.
+    Whole(usize),
+
+    /// The package's test files are split across every shard with vitest's `--shard`.
+    Split,
+}
+
+/// Durations in milliseconds of each package's tests in previous runs.
+pub type Durations = BTreeMap<String, f64>;
+
+/// The durations file used when `--durations` isn't given.
+pub fn default_durations_path(ws: &Workspace) -> PathBuf {
+    ws.root.join("test-durations.json")
+}
+
+/// Loads the durations at `path`, or none if the file doesn't exist.
+pub fn load_durations(path: &Path) -> Result<Durations> {
+    if !path.exists() {
+        return Ok(Durations::new());
+    }
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read test durations: {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid test durations: {}", path.display()))
+}
+
+/// Adds `measured` to the durations at `path`, keeping the durations of other packages.
+pub fn save_durations(path: &Path, measured: &Durations) -> Result<()> {
+    let mut durations = load_durations(path)?;
+    durations.extend(measured.clone());
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        utils::create_dir_if_missing(parent)?;
+    }
+    utils::write(path, serde_json::to_string_pretty(&durations)?)
+}
+
+/// Assigns each of `packages` to a shard, given the `durations` of previous runs.
+///
+/// Packages without a recorded duration are assumed to take the average duration of the others.
+/// The result only depends on the inputs, so every machine computes the same assignment.
+pub fn assign(
+    packages: &[PackageName],
+    durations: &Durations,
+    count: usize,
+) -> BTreeMap<PackageName, Assignment> {
+    #[allow(clippy::cast_precision_loss)]
+    let default = if durations.is_empty() {
+        1.
+    } else {
+        durations.values().sum::<f64>() / durations.len() as f64
+    };
+    let mut weighted = packages
+        .iter()
+        .map(|pkg| {
+            let weight = durations.get(&pkg.to_string()).copied().unwrap_or(default);
+            (pkg, weight)
+        })
+        .collect::<Vec<_>>();
+    weighted.sort_by(|(a, wa), (b, wb)| wb.total_cmp(wa).then_with(|| a.cmp(b)));
+
+    #[allow(clippy::cast_precision_loss)]
+    let fair_share = weighted.iter().map(|(_, w)| w).sum::<f64>() / count as f64;
+
+    let mut loads = vec![0.; count];
+    let mut assignments = BTreeMap::new();
+    for (pkg, weight) in weighted {
+        let assignment = if count > 1 && weight > fair_share {
+            for load in &mut loads {
+                #[allow(clippy::cast_precision_loss)]
+                let share = weight / count as f64;
+                *load += share;
+            }
+            Assignment::Split
+        } else {
+            let (shard, _) = loads
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                .unwrap();
+            loads[shard] += weight;
+            Assignment::Whole(shard + 1)
+        };
+        assignments.insert(pkg.clone(), assignment);
+    }
+    assignments
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn name(name: &str) -> PackageName {
+        PackageName {
+            name: name.into(),
+            scope: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_shard() {
+        assert_eq!(
+            "2/4".parse::<Shard>().unwrap(),
+            Shard { index: 2, count: 4 }
+        );
+        assert!("0/4".parse::<Shard>().is_err());
+        assert!("5/4".parse::<Shard>().is_err());
+        assert!("2".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    fn test_assign() {
+        let packages = ["a", "b", "c", "d", "e"].map(name);
+        let durations = Durations::from([
+            ("a".into(), 1200.),
+            ("b".into(), 300.),
+            ("c".into(), 200.),
+            ("d".into(), 100.),
+        ]);
+        let assignments = assign(&packages, &durations, 2);
+        assert_eq!(assignments[&name("a")], Assignment::Split);
+        // e has no history, so it's assumed to take the average of 450ms.
+        assert_eq!(assignments[&name("e")], Assignment::Whole(1));
+        assert_eq!(assignments[&name("b")], Assignment::Whole(2));
+        assert_eq!(assignments[&name("c")], Assignment::Whole(2));
+        assert_eq!(assignments[&name("d")], Assignment::Whole(1));
+
+        let assignments = assign(&packages, &Durations::new(), 1);
+        assert!(assignments.values().all(|a| *a == Assignment::Whole(1)));
+    }
+}
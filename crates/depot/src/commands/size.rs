@@ -0,0 +1,247 @@
+//! Bundle size measurement and budgets for built packages.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail, ensure};
+use globset::Glob;
+
+use super::artifacts::ArtifactManifest;
+use crate::{utils, workspace::package::Package};
+
+/// Computes the compressed sizes of files with Node's zlib, since depot already requires Node.
+const MEASURE_SCRIPT: &str = r#"
+const fs = require("fs");
+const zlib = require("zlib");
+const sizes = {};
+for (const file of process.argv.slice(1)) {
+  const contents = fs.readFileSync(file);
+  sizes[file] = {
+    raw: contents.length,
+    gzip: zlib.gzipSync(contents, { level: 9 }).length,
+    brotli: zlib.brotliCompressSync(contents).length,
+  };
+}
+console.log(JSON.stringify(sizes));
+"#;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FileSize {
+    pub raw: u64,
+    pub gzip: u64,
+    pub brotli: u64,
+}
+
+/// Sizes of every file in a package's `dist/`, keyed by path relative to the package root.
+pub type SizeReport = BTreeMap<String, FileSize>;
+
+fn history_path(pkg: &Package) -> PathBuf {
+    pkg.root
+        .join("node_modules")
+        .join(".depot")
+        .join("sizes.json")
+}
+
+pub async fn measure(pkg: &Package, manifest: &ArtifactManifest) -> Result<SizeReport> {
+    let files = manifest
+        .files
+        .keys()
+        .filter(|file| Path::new(file).extension().is_none_or(|ext| ext != "map"))
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        return Ok(SizeReport::new());
+    }
+
+    let node = utils::find_node().context("Failed to find `node` to measure bundle sizes")?;
+    let output = tokio::process::Command::new(node)
+        .current_dir(&pkg.root)
+        .args(["-e", MEASURE_SCRIPT])
+        .args(&files)
+        .output()
+        .await?;
+    ensure!(
+        output.status.success(),
+        "Failed to measure bundle sizes: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let sizes: BTreeMap<PathBuf, FileSize> = serde_json::from_slice(&output.stdout)?;
+    Ok(sizes
+        .into_iter()
+        .map(|(path, size)| (path.display().to_string().replace('\\', "/"), size))
+        .collect())
+}
+
+/// Loads the report recorded by the previous build of `pkg`, if any.
+pub fn load_previous(pkg: &Package) -> Option<SizeReport> {
+    let contents = fs::read_to_string(history_path(pkg)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+pub fn save(pkg: &Package, report: &SizeReport) -> Result<()> {
+    let path = history_path(pkg);
+    utils::create_dir_if_missing(path.parent().unwrap())?;
+    utils::write(path, serde_json::to_string_pretty(report)?)
+}
+
+/// Parses a size like `"150kB"`, `"1.5 MB"`, `"64 KiB"` or `"800B"` into a number of bytes.
+///
+/// `kB` and `MB` are decimal units of 1000 bytes, while `KiB` and `MiB` are binary units of 1024.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num: f64 = num
+        .parse()
+        .with_context(|| format!("Invalid size: `{s}`"))?;
+    let multiplier = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1.,
+        "kb" => 1000.,
+        "kib" => 1024.,
+        "mb" => 1000. * 1000.,
+        "mib" => 1024. * 1024.,
+        _ => bail!("Invalid size unit in `{s}`, expected B, kB, KiB, MB or MiB"),
+    };
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok((num * multiplier).round() as u64)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn format_size(bytes: u64) -> String {
+    if bytes < 1000 {
+        format!("{bytes} B")
+    } else if bytes < 1000 * 1000 {
+        format!("{:.1} kB", bytes as f64 / 1000.)
+    } else {
+        format!("{:.2} MB", bytes as f64 / (1000. * 1000.))
+    }
+}
+
+fn format_delta(current: u64, previous: u64) -> String {
+    match current.cmp(&previous) {
+        std::cmp::Ordering::Equal => String::new(),
+        std::cmp::Ordering::Greater => format!("+{}", format_size(current - previous)),
+        std::cmp::Ordering::Less => format!("-{}", format_size(previous - current)),
+    }
+}
+
+/// Renders `report` as a table, with the change in gzip size since `previous`.
+pub fn format_table(pkg: &Package, report: &SizeReport, previous: Option<&SizeReport>) -> String {
+    let header = ["file", "raw", "gzip", "brotli", "change"];
+    let rows = report
+        .iter()
+        .map(|(file, size)| {
+            let change = match previous.map(|previous| previous.get(file)) {
+                Some(Some(prev)) => format_delta(size.gzip, prev.gzip),
+                Some(None) => "new".to_string(),
+                None => String::new(),
+            };
+            [
+                file.clone(),
+                format_size(size.raw),
+                format_size(size.gzip),
+                format_size(size.brotli),
+                change,
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let widths = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .chain([header[i].len()])
+                .max()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = format!("Bundle sizes for {}:\n", pkg.name);
+    let rows = std::iter::once(header.map(String::from)).chain(rows);
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, width))| {
+                if i == 0 {
+                    format!("{cell:<width$}")
+                } else {
+                    format!("{cell:>width$}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(out, "  {}", line.trim_end()).unwrap();
+    }
+    out
+}
+
+/// Checks the gzip size of the files matching each glob against its budget,
+/// returning a message for every budget that is exceeded.
+pub fn check_budgets(
+    report: &SizeReport,
+    budgets: &indexmap::IndexMap<String, String>,
+) -> Result<Vec<String>> {
+    let mut violations = Vec::new();
+    for (glob, limit) in budgets {
+        let matcher = Glob::new(glob)
+            .with_context(|| format!("Invalid glob in size-limit: `{glob}`"))?
+            .compile_matcher();
+        let limit_bytes = parse_size(limit)?;
+        let total = report
+            .iter()
+            .filter(|(file, _)| matcher.is_match(file))
+            .map(|(_, size)| size.gzip)
+            .sum::<u64>();
+        if total > limit_bytes {
+            violations.push(format!(
+                "`{glob}` is {} gzipped, exceeding its budget of {limit}",
+                format_size(total)
+            ));
+        }
+    }
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("800B").unwrap(), 800);
+        assert_eq!(parse_size("150kB").unwrap(), 150_000);
+        assert_eq!(parse_size("64 KiB").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("1.5 MB").unwrap(), 1_500_000);
+        assert_eq!(parse_size("2MiB").unwrap(), 2 * 1024 * 1024);
+        assert!(parse_size("10 parsecs").is_err());
+    }
+
+    #[test]
+    fn test_check_budgets() {
+        let size = |gzip| FileSize {
+            raw: gzip * 3,
+            gzip,
+            brotli: gzip,
+        };
+        let report = SizeReport::from([
+            ("dist/index.js".to_string(), size(100_000)),
+            ("dist/vendor.js".to_string(), size(60_000)),
+            ("dist/style.css".to_string(), size(10_000)),
+        ]);
+        let budgets = indexmap::indexmap! {
+            "dist/*.js".to_string() => "150kB".to_string(),
+            "dist/*.css".to_string() => "20kB".to_string(),
+        };
+        let violations = check_budgets(&report, &budgets).unwrap();
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].starts_with("`dist/*.js` is 160.0 kB gzipped"));
+    }
+}
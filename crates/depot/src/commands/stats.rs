@@ -0,0 +1,141 @@
+//! Statistics for deciding whether two sets of benchmark samples differ.
+
+/// Summary statistics of a set of samples.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Summary {
+    pub mean: f64,
+
+    /// Sample standard deviation.
+    pub sd: f64,
+    pub samples: usize,
+}
+
+/// Natural log of the gamma function, via the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.120_865_097_386_617_9e-2,
+        -0.539_523_938_495_3e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut y = x;
+    let mut series = 1.000_000_000_190_015;
+    for c in COEFFICIENTS {
+        y += 1.;
+        series += c / y;
+    }
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// Continued fraction for the incomplete beta function, evaluated with Lentz's method.
+#[allow(clippy::many_single_char_names)]
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const MAX_ITERATIONS: u32 = 200;
+    const EPSILON: f64 = 3e-14;
+    const TINY: f64 = 1e-300;
+
+    let clamp = |v: f64| if v.abs() < TINY { TINY } else { v };
+    let mut c = 1.;
+    let mut d = 1. / clamp(1. - (a + b) * x / (a + 1.));
+    let mut h = d;
+    for m in 1..=MAX_ITERATIONS {
+        let m = f64::from(m);
+        let numerator = m * (b - m) * x / ((a + 2. * m - 1.) * (a + 2. * m));
+        d = 1. / clamp(1. + numerator * d);
+        c = clamp(1. + numerator / c);
+        h *= d * c;
+
+        let numerator = -(a + m) * (a + b + m) * x / ((a + 2. * m) * (a + 2. * m + 1.));
+        d = 1. / clamp(1. + numerator * d);
+        c = clamp(1. + numerator / c);
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.).abs() < EPSILON {
+            break;
+        }
+    }
+    h
+}
+
+/// The regularized incomplete beta function `I_x(a, b)`.
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0. {
+        return 0.;
+    }
+    if x >= 1. {
+        return 1.;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1. - x).ln()).exp();
+    if x < (a + 1.) / (a + b + 2.) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1. - front * beta_continued_fraction(1. - x, b, a) / b
+    }
+}
+
+/// Two-tailed p-value of Student's t-distribution with `df` degrees of freedom.
+fn t_test_p_value(t: f64, df: f64) -> f64 {
+    incomplete_beta(df / (df + t * t), df / 2., 0.5)
+}
+
+/// Returns the p-value of Welch's t-test for whether `a` and `b` have different means,
+/// or `None` if there are too few samples to tell.
+#[allow(clippy::cast_precision_loss)]
+pub fn welch_t_test(a: &Summary, b: &Summary) -> Option<f64> {
+    if a.samples < 2 || b.samples < 2 {
+        return None;
+    }
+    let (na, nb) = (a.samples as f64, b.samples as f64);
+    let (va, vb) = (a.sd * a.sd / na, b.sd * b.sd / nb);
+    let se = (va + vb).sqrt();
+    if se == 0. {
+        return Some(if a.mean.total_cmp(&b.mean).is_eq() {
+            1.
+        } else {
+            0.
+        });
+    }
+    let t = (a.mean - b.mean) / se;
+    let df = (va + vb).powi(2) / (va * va / (na - 1.) + vb * vb / (nb - 1.));
+    Some(t_test_p_value(t, df))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {expected}, found {actual}"
+        );
+    }
+
+    #[test]
+    fn test_t_distribution() {
+        assert_close(ln_gamma(5.), 24f64.ln());
+        assert_close(t_test_p_value(0., 10.), 1.);
+        // Critical values of the two-tailed t-test at the 5% level.
+        assert_close(t_test_p_value(2.228, 10.), 0.05);
+        assert_close(t_test_p_value(1.960, 1e6), 0.05);
+    }
+
+    #[test]
+    fn test_welch_t_test() {
+        let a = Summary {
+            mean: 10.,
+            sd: 1.,
+            samples: 100,
+        };
+        let b = Summary { mean: 10.1, ..a };
+        let c = Summary { mean: 11., ..a };
+        assert!(welch_t_test(&a, &b).unwrap() > 0.05);
+        assert!(welch_t_test(&a, &c).unwrap() < 0.001);
+        assert_eq!(welch_t_test(&a, &Summary { samples: 1, ..c }), None);
+    }
+}
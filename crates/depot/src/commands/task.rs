@@ -0,0 +1,198 @@
+use std::{fmt, path::PathBuf};
+
+use anyhow::{Context, Result, bail, ensure};
+use clap::CommandFactory;
+
+use super::CommandParser;
+use crate::workspace::{
+    Command, CommandRuntime, CoreCommand, PackageCommand, Workspace,
+    dep_graph::DepGraph,
+    package::{Package, TaskConfig},
+};
+
+/// Run a user-defined task from the "tasks" entry of each package's depot config
+#[derive(clap::Parser, Debug)]
+pub struct TaskArgs {
+    /// Name of the task to run
+    pub name: String,
+}
+
+pub struct TaskCommand {
+    name: String,
+    deps: Vec<Command>,
+    runtime: CommandRuntime,
+}
+
+impl TaskCommand {
+    /// Resolves the task `name` across all packages in the workspace.
+    ///
+    /// Dependencies on other user-defined tasks take precedence over built-in commands
+    /// of the same name, and are checked for cycles up front.
+    pub fn new(name: &str, ws: &Workspace) -> Result<Self> {
+        let task_graph = DepGraph::build(vec![name.to_string()], Clone::clone, |task: &String| {
+            task_configs(ws, task)
+                .flat_map(|(_, config)| config.deps.iter())
+                .filter(|dep| defines_task(ws, dep))
+                .cloned()
+                .collect()
+        })
+        .context("Task dependencies contain a cycle")?;
+
+        for task in task_graph.nodes() {
+            Self::validate(task, ws)?;
+        }
+
+        Self::resolve(name, ws)
+    }
+
+    fn validate(name: &str, ws: &Workspace) -> Result<()> {
+        let builtins = CommandParser::command();
+        let is_builtin = builtins.get_subcommands().any(|subcmd| {
+            subcmd.get_name() == name || subcmd.get_all_aliases().any(|alias| alias == name)
+        });
+        ensure!(
+            !is_builtin,
+            "Task `{name}` has the same name as a built-in command"
+        );
+
+        let mut runtimes = task_configs(ws, name).map(|(pkg, config)| (pkg, config.runtime));
+        let Some((_, runtime)) = runtimes.next() else {
+            bail!("No package defines a task named `{name}`");
+        };
+        if let Some((pkg, _)) = runtimes.find(|(_, other)| *other != runtime) {
+            bail!(
+                "Task `{name}` in package `{}` has a different runtime than other packages",
+                pkg.name
+            );
+        }
+
+        for (pkg, config) in task_configs(ws, name) {
+            for glob in config.inputs.iter().chain(&config.outputs) {
+                globset::Glob::new(glob).with_context(|| {
+                    format!(
+                        "Invalid glob `{glob}` in task `{name}` of package `{}`",
+                        pkg.name
+                    )
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve(name: &str, ws: &Workspace) -> Result<Self> {
+        let mut dep_names = task_configs(ws, name)
+            .flat_map(|(_, config)| config.deps.iter())
+            .collect::<Vec<_>>();
+        dep_names.sort();
+        dep_names.dedup();
+
+        let deps = dep_names
+            .into_iter()
+            .map(|dep| {
+                if defines_task(ws, dep) {
+                    Ok(TaskCommand::resolve(dep, ws)?.kind())
+                } else {
+                    super::Command::parse_str(dep)
+                        .with_context(|| format!("Invalid dependency `{dep}` of task `{name}`"))?
+                        .kind(ws)
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let (_, config) = task_configs(ws, name).next().unwrap();
+        Ok(TaskCommand {
+            name: name.to_string(),
+            deps,
+            runtime: config.runtime,
+        })
+    }
+
+    pub fn kind(self) -> Command {
+        Command::package(self)
+    }
+
+    fn config<'a>(&self, pkg: &'a Package) -> Option<&'a TaskConfig> {
+        pkg.manifest.config.tasks.as_ref()?.get(&self.name)
+    }
+}
+
+fn task_configs<'a>(
+    ws: &'a Workspace,
+    name: &'a str,
+) -> impl Iterator<Item = (&'a Package, &'a TaskConfig)> + 'a {
+    ws.packages.iter().filter_map(move |pkg| {
+        let config = pkg.manifest.config.tasks.as_ref()?.get(name)?;
+        Some((pkg, config))
+    })
+}
+
+fn defines_task(ws: &Workspace, name: &str) -> bool {
+    task_configs(ws, name).next().is_some()
+}
+
+impl fmt::Debug for TaskCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskCommand")
+            .field("name", &self.name)
+            .field("runtime", &self.runtime)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CoreCommand for TaskCommand {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl PackageCommand for TaskCommand {
+    async fn run_pkg(&self, pkg: &Package) -> Result<()> {
+        let Some(config) = self.config(pkg) else {
+            return Ok(());
+        };
+
+        let args = shlex::split(&config.command)
+            .with_context(|| format!("Failed to parse command of task `{}`", self.name))?;
+        let Some((program, args)) = args.split_first() else {
+            bail!("Task `{}` has an empty command", self.name);
+        };
+
+        pkg.exec(program, |cmd| {
+            cmd.args(args);
+        })
+        .await
+    }
+
+    fn deps(&self) -> Vec<Command> {
+        self.deps.clone()
+    }
+
+    fn runtime(&self) -> CommandRuntime {
+        self.runtime
+    }
+
+    fn input_files(&self, pkg: &Package) -> Option<Vec<PathBuf>> {
+        let Some(config) = self.config(pkg) else {
+            return Some(Vec::new());
+        };
+
+        // Outputs that don't exist yet (e.g. after `depot clean`) must be regenerated.
+        for output in &config.outputs {
+            if pkg
+                .glob_files(std::slice::from_ref(output))
+                .ok()?
+                .is_empty()
+            {
+                return None;
+            }
+        }
+
+        if config.inputs.is_empty() {
+            Some(pkg.all_files().collect())
+        } else {
+            pkg.glob_files(&config.inputs).ok()
+        }
+    }
+}
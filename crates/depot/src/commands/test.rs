@@ -1,6 +1,26 @@
-use super::build::{BuildArgs, BuildCommand};
-use crate::workspace::{Command, CommandRuntime, CoreCommand, PackageCommand, package::Package};
-use anyhow::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+
+use super::{
+    build::{BuildArgs, BuildCommand},
+    coverage::{self, FileCoverage},
+    init::{InitArgs, InitCommand},
+    junit,
+    shard::{self, Assignment, Durations, Shard},
+    test_report::{self, PackageReport, VitestReport},
+};
+use crate::{
+    utils,
+    workspace::{
+        Command, CommandRuntime, CoreCommand, PackageCommand, Workspace,
+        package::{Package, PackageName},
+    },
+};
+use anyhow::{Context, Result, ensure};
+use futures::future::try_join;
 
 /// Run tests via vitest
 #[derive(clap::Parser, Default, Debug)]
@@ -9,6 +29,23 @@ pub struct TestArgs {
     #[clap(short, long, action)]
     watch: bool,
 
+    /// Write every package's results to this path as a single junit-style XML report
+    #[arg(long)]
+    junit: Option<PathBuf>,
+
+    /// Collect code coverage with vitest's v8 provider, and write a merged report to `coverage/`
+    #[arg(long, conflicts_with = "watch")]
+    coverage: bool,
+
+    /// Only run this shard of the tests, e.g. `2/4` for the second of four CI machines
+    #[arg(long)]
+    shard: Option<Shard>,
+
+    /// File of previous test durations that balances shards, `test-durations.json` in the
+    /// workspace root by default. Runs without `--shard` write their durations to it if given.
+    #[arg(long)]
+    durations: Option<PathBuf>,
+
     /// Additional arguments to pass to vitest
     #[arg(last = true)]
     pub vitest_args: Option<String>,
@@ -17,21 +54,153 @@ pub struct TestArgs {
 #[derive(Debug)]
 pub struct TestCommand {
     args: TestArgs,
+
+    /// In watch mode, each package is also rebuilt when it changes.
+    build: Option<BuildCommand>,
+    reports: Mutex<Vec<PackageReport>>,
+
+    /// Packages without any files matching their test globs.
+    no_tests: Mutex<Vec<PackageName>>,
+    coverage: Mutex<Vec<(PackageName, Vec<FileCoverage>)>>,
+    shard_assignments: OnceLock<BTreeMap<PackageName, Assignment>>,
+
+    /// How long each package's tests took in this run.
+    durations: Mutex<Durations>,
 }
 
 impl CoreCommand for TestCommand {
     fn name(&self) -> String {
         "test".into()
     }
+
+    fn finish(&self, ws: &Workspace) -> Result<()> {
+        // Written even if no package produced a report, e.g. when every task was skipped,
+        // so CI always finds a valid file.
+        self.write_junit(ws)?;
+
+        if let Some(path) = &self.args.durations
+            && self.args.shard.is_none()
+        {
+            shard::save_durations(path, &self.durations.lock().unwrap())?;
+        }
+
+        if !self.args.coverage {
+            return Ok(());
+        }
+
+        let mut packages = self.coverage.lock().unwrap();
+        packages.sort_by(|(a, _), (b, _)| a.cmp(b));
+        coverage::write_reports(&ws.root, &packages)?;
+
+        if let Some(thresholds) = &ws.config.coverage_thresholds {
+            let summary = coverage::summarize(packages.iter().flat_map(|(_, files)| files));
+            let violations = coverage::check_thresholds(&summary, thresholds);
+            ensure!(
+                violations.is_empty(),
+                "Workspace coverage is below its thresholds:\n  {}",
+                violations.join("\n  ")
+            );
+        }
+
+        Ok(())
+    }
+
+    fn summary(&self) -> Option<String> {
+        let mut reports = self.reports.lock().unwrap().clone();
+        reports.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut no_tests = self.no_tests.lock().unwrap().clone();
+        no_tests.sort();
+        let mut sections = Vec::new();
+        if !reports.is_empty() || !no_tests.is_empty() {
+            sections.push(test_report::format_summary(&reports, &no_tests));
+        }
+
+        let coverage = self.coverage.lock().unwrap();
+        if !coverage.is_empty() {
+            sections.push(coverage::format_table(&coverage));
+        }
+
+        (!sections.is_empty()).then(|| sections.join("\n\n"))
+    }
 }
 
 #[async_trait::async_trait]
 impl PackageCommand for TestCommand {
     async fn run_pkg(&self, pkg: &Package) -> Result<()> {
-        if !pkg.root.join("tests").exists() {
+        match &self.build {
+            Some(build) => try_join(build.run_pkg(pkg), self.test_pkg(pkg))
+                .await
+                .map(|_| ()),
+            None => self.test_pkg(pkg).await,
+        }
+    }
+
+    fn uses_dev_deps(&self) -> bool {
+        true
+    }
+
+    /// With coverage enabled, every package must run so that the merged report is complete.
+    /// A shard only runs some of the tests, so its result can't make a package fresh.
+    fn input_files(&self, pkg: &Package) -> Option<Vec<PathBuf>> {
+        (!self.args.coverage && self.args.shard.is_none()).then(|| pkg.all_files().collect())
+    }
+
+    fn deps(&self) -> Vec<Command> {
+        // A watching build never finishes, so in watch mode it runs as part of each test task.
+        if self.args.watch {
+            vec![InitCommand::new(InitArgs::default()).kind()]
+        } else {
+            vec![BuildCommand::new(BuildArgs::default()).kind()]
+        }
+    }
+
+    fn runtime(&self) -> CommandRuntime {
+        if self.args.watch {
+            CommandRuntime::RunForever
+        } else {
+            CommandRuntime::WaitForDependencies
+        }
+    }
+}
+
+impl TestCommand {
+    pub fn new(args: TestArgs) -> Self {
+        let build = args.watch.then(|| {
+            BuildCommand::new(BuildArgs {
+                watch: true,
+                ..Default::default()
+            })
+        });
+        TestCommand {
+            args,
+            build,
+            reports: Mutex::new(Vec::new()),
+            no_tests: Mutex::new(Vec::new()),
+            coverage: Mutex::new(Vec::new()),
+            shard_assignments: OnceLock::new(),
+            durations: Mutex::new(Durations::new()),
+        }
+    }
+
+    async fn test_pkg(&self, pkg: &Package) -> Result<()> {
+        if pkg.test_files()?.is_empty() {
+            self.no_tests.lock().unwrap().push(pkg.name.clone());
             return Ok(());
         }
 
+        let vitest_shard = match self.args.shard {
+            Some(shard) => match self.shard_assignment(pkg, shard)? {
+                Assignment::Whole(index) if index == shard.index => None,
+                Assignment::Whole(_) => return Ok(()),
+                Assignment::Split => Some(shard),
+            },
+            None => None,
+        };
+
+        if self.args.coverage {
+            coverage::check_provider(pkg)?;
+        }
+
         let vitest_args = match &self.args.vitest_args {
             Some(vitest_args) => {
                 Some(shlex::split(vitest_args).context("Failed to parse vitest args")?)
@@ -39,38 +208,232 @@ impl PackageCommand for TestCommand {
             None => None,
         };
 
-        pkg.exec("vitest", |cmd| {
+        // vitest combines filters with OR, so ours would undo any filter the user passed.
+        let test_filters = match &vitest_args {
+            Some(vitest_args) if has_filter(vitest_args) => Vec::new(),
+            _ => glob_filters(&pkg.test_globs()),
+        };
+
+        // The JSON report is only read once vitest exits, which it never does in watch mode.
+        let report_path = (!self.args.watch).then(|| test_report::report_path(pkg));
+        if let Some(report_path) = &report_path {
+            utils::create_dir_if_missing(report_path.parent().unwrap())?;
+            if report_path.exists() {
+                std::fs::remove_file(report_path)?;
+            }
+        }
+
+        let configure = |cmd: &mut tokio::process::Command| {
             let subcmd = if self.args.watch { "watch" } else { "run" };
             cmd.arg(subcmd);
+            cmd.args(&test_filters);
 
+            // Reported as having no tests below, rather than as a failure.
             cmd.arg("--passWithNoTests");
 
-            if let Some(vitest_args) = vitest_args {
-                cmd.args(vitest_args);
+            if let Some(report_path) = &report_path {
+                cmd.args([
+                    "--reporter=default",
+                    "--reporter=json",
+                    "--includeTaskLocation",
+                ]);
+                cmd.arg(format!("--outputFile.json={}", report_path.display()));
             }
-        })
-        .await
-    }
 
-    fn deps(&self) -> Vec<Command> {
-        vec![BuildCommand::new(BuildArgs::default()).kind()]
-    }
+            if let Some(shard) = vitest_shard {
+                cmd.arg(format!("--shard={shard}"));
+            }
 
-    fn runtime(&self) -> CommandRuntime {
+            if self.args.coverage {
+                cmd.args([
+                    "--coverage.enabled",
+                    "--coverage.provider=v8",
+                    "--coverage.reporter=json",
+                    "--coverage.include=src/**",
+                ]);
+                let reports_dir = coverage::reports_dir(pkg);
+                cmd.arg(format!(
+                    "--coverage.reportsDirectory={}",
+                    reports_dir.display()
+                ));
+            }
+
+            if let Some(vitest_args) = &vitest_args {
+                cmd.args(vitest_args);
+            }
+        };
+
+        // vitest doesn't watch `node_modules`, so it's restarted once a dependency is rebuilt.
+        // It also waits for the first build of each dependency, to not import stale outputs.
+        let mut upstream = pkg.upstream_outputs();
         if self.args.watch {
-            CommandRuntime::RunForever
+            upstream.ready().await;
+        }
+        let mut process = pkg.start_process("vitest", configure)?;
+        let result = if self.args.watch {
+            loop {
+                tokio::select! {
+                  result = process.wait_for_success() => break result,
+                  () = upstream.changed() => {
+                    process = pkg.replace_process(&process, "vitest", configure)?;
+                  }
+                }
+            }
         } else {
-            CommandRuntime::WaitForDependencies
+            process.wait_for_success().await
+        };
+
+        let report = match &report_path {
+            Some(report_path) => VitestReport::load(report_path)?,
+            None => None,
+        };
+        // None of the matched files were tests, e.g. a `tests/` directory with only fixtures.
+        if result.is_ok()
+            && report_path.is_some()
+            && report
+                .as_ref()
+                .is_none_or(|report| report.test_results.is_empty())
+        {
+            self.no_tests.lock().unwrap().push(pkg.name.clone());
+            return Ok(());
+        }
+
+        if let Some(report) = report {
+            let mut reports = self.reports.lock().unwrap();
+            // Only complete runs are representative of how long a package's tests take.
+            if vitest_shard.is_none() {
+                self.durations
+                    .lock()
+                    .unwrap()
+                    .insert(pkg.name.to_string(), report.duration());
+            }
+            reports.push(PackageReport {
+                name: pkg.name.clone(),
+                root: pkg.root.clone(),
+                report,
+            });
+        }
+
+        // Rewritten after each package, so the file is complete even if a later package fails.
+        self.write_junit(pkg.workspace())?;
+
+        result?;
+
+        if self.args.coverage {
+            let files = coverage::load(pkg, &pkg.workspace().root)?;
+            if let Some(thresholds) = &pkg.manifest.config.coverage_thresholds {
+                let violations =
+                    coverage::check_thresholds(&coverage::summarize(&files), thresholds);
+                ensure!(
+                    violations.is_empty(),
+                    "Coverage of {} is below its thresholds:\n  {}",
+                    pkg.name,
+                    violations.join("\n  ")
+                );
+            }
+            self.coverage
+                .lock()
+                .unwrap()
+                .push((pkg.name.clone(), files));
         }
+
+        Ok(())
     }
-}
 
-impl TestCommand {
-    pub fn new(args: TestArgs) -> Self {
-        TestCommand { args }
+    fn write_junit(&self, ws: &Workspace) -> Result<()> {
+        let Some(junit_path) = &self.args.junit else {
+            return Ok(());
+        };
+        let mut reports = self.reports.lock().unwrap();
+        reports.sort_by(|a, b| a.name.cmp(&b.name));
+        utils::write(junit_path, junit::format_junit(&reports, &ws.root))
+    }
+
+    fn shard_assignment(&self, pkg: &Package, shard: Shard) -> Result<Assignment> {
+        if let Some(assignments) = self.shard_assignments.get() {
+            return Ok(assignments[&pkg.name]);
+        }
+
+        let ws = pkg.workspace();
+        let durations_path = match &self.args.durations {
+            Some(path) => path.clone(),
+            None => shard::default_durations_path(ws),
+        };
+        let durations = shard::load_durations(&durations_path)?;
+        let mut packages = ws
+            .pkg_graph
+            .nodes()
+            .filter(|pkg| pkg.test_files().is_ok_and(|files| !files.is_empty()))
+            .map(|pkg| pkg.name.clone())
+            .collect::<Vec<_>>();
+        packages.sort();
+        let assignments = self
+            .shard_assignments
+            .get_or_init(|| shard::assign(&packages, &durations, shard.count));
+        Ok(assignments[&pkg.name])
     }
 
     pub fn kind(self) -> Command {
         Command::package(self)
     }
 }
+
+/// Whether any of the vitest args is a positional filter. Option values passed as a separate
+/// arg, like `--reporter verbose`, also count, which only means the test globs aren't applied.
+fn has_filter(vitest_args: &[String]) -> bool {
+    vitest_args.iter().any(|arg| !arg.starts_with('-'))
+}
+
+/// Filters for vitest that narrow its test files to the given globs, one per glob rather than
+/// one per file to keep the command line short. Each is the part of the glob up to the directory
+/// containing its first wildcard, which vitest matches as a path prefix. vitest's own `include`
+/// patterns still apply to the files under it.
+fn glob_filters(globs: &[String]) -> Vec<String> {
+    let mut filters = Vec::new();
+    for glob in globs {
+        let filter = match glob.find(['*', '?', '[', '{']) {
+            Some(index) => match glob[..index].rfind('/') {
+                Some(slash) => &glob[..=slash],
+                // The glob matches anywhere in the package, so there is nothing to narrow.
+                None => return Vec::new(),
+            },
+            None => glob.as_str(),
+        };
+        if !filters.iter().any(|f| f == filter) {
+            filters.push(filter.to_string());
+        }
+    }
+    filters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_glob_filters() {
+        assert_eq!(
+            glob_filters(&strings(&["tests/**", "src/**/*.{test,spec}.{ts,tsx}"])),
+            strings(&["tests/", "src/"])
+        );
+        assert_eq!(
+            glob_filters(&strings(&[
+                "tests/add.test.ts",
+                "tests/*.test.ts",
+                "tests/unit/**"
+            ])),
+            strings(&["tests/add.test.ts", "tests/", "tests/unit/"])
+        );
+        assert!(glob_filters(&strings(&["tests/**", "**/*.test.ts"])).is_empty());
+    }
+
+    #[test]
+    fn test_has_filter() {
+        assert!(has_filter(&strings(&["--bail=1", "add"])));
+        assert!(!has_filter(&strings(&["--bail=1", "--reporter=verbose"])));
+    }
+}
@@ -0,0 +1,327 @@
+//! Parsing and aggregating the results of vitest's JSON reporter.
+
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::workspace::package::{Package, PackageName};
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    #[serde(other)]
+    Skipped,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct Location {
+    pub line: u32,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResult {
+    pub full_name: String,
+    pub status: TestStatus,
+
+    /// Duration in milliseconds.
+    #[serde(default)]
+    pub duration: Option<f64>,
+
+    #[serde(default)]
+    pub failure_messages: Vec<String>,
+
+    #[serde(default)]
+    pub location: Option<Location>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FileResult {
+    /// Absolute path to the test file.
+    pub name: PathBuf,
+    pub status: TestStatus,
+
+    /// An error that prevented the file's tests from running, like a failed import.
+    #[serde(default)]
+    pub message: String,
+
+    #[serde(default)]
+    pub start_time: Option<f64>,
+
+    #[serde(default)]
+    pub end_time: Option<f64>,
+
+    pub assertion_results: Vec<AssertionResult>,
+}
+
+impl FileResult {
+    /// Duration of the whole file in milliseconds, if reported.
+    pub fn duration(&self) -> Option<f64> {
+        Some(self.end_time? - self.start_time?)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VitestReport {
+    pub test_results: Vec<FileResult>,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Counts {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+/// A test that failed, or a test file that failed to run.
+pub struct Failure {
+    pub file: String,
+    pub line: Option<u32>,
+    pub name: Option<String>,
+    pub message: String,
+}
+
+impl VitestReport {
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path)?;
+        let report = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse vitest report: {}", path.display()))?;
+        Ok(Some(report))
+    }
+
+    /// Total duration of all test files in milliseconds.
+    pub fn duration(&self) -> f64 {
+        self.test_results
+            .iter()
+            .filter_map(FileResult::duration)
+            .sum()
+    }
+
+    pub fn counts(&self) -> Counts {
+        let mut counts = Counts::default();
+        for test in self
+            .test_results
+            .iter()
+            .flat_map(|file| &file.assertion_results)
+        {
+            match test.status {
+                TestStatus::Passed => counts.passed += 1,
+                TestStatus::Failed => counts.failed += 1,
+                TestStatus::Skipped => counts.skipped += 1,
+            }
+        }
+        counts.failed += self
+            .test_results
+            .iter()
+            .filter(|file| file.status == TestStatus::Failed && file.assertion_results.is_empty())
+            .count();
+        counts
+    }
+
+    /// Returns the failures in the report, with file paths relative to `root`.
+    pub fn failures(&self, root: &Path) -> Vec<Failure> {
+        let mut failures = Vec::new();
+        for file in &self.test_results {
+            let rel_path = file.name.strip_prefix(root).unwrap_or(&file.name);
+            let rel_path = rel_path.display().to_string().replace('\\', "/");
+            if file.status == TestStatus::Failed && file.assertion_results.is_empty() {
+                failures.push(Failure {
+                    file: rel_path.clone(),
+                    line: None,
+                    name: None,
+                    message: file.message.clone(),
+                });
+            }
+            for test in &file.assertion_results {
+                if test.status != TestStatus::Failed {
+                    continue;
+                }
+                failures.push(Failure {
+                    file: rel_path.clone(),
+                    line: test.location.map(|loc| loc.line),
+                    name: Some(test.full_name.clone()),
+                    message: test.failure_messages.join("\n"),
+                });
+            }
+        }
+        failures
+    }
+}
+
+/// The results of running the tests of one package.
+#[derive(Clone, Debug)]
+pub struct PackageReport {
+    pub name: PackageName,
+    pub root: PathBuf,
+    pub report: VitestReport,
+}
+
+/// Where vitest's JSON reporter writes the results for `pkg`.
+pub fn report_path(pkg: &Package) -> PathBuf {
+    pkg.root
+        .join("node_modules")
+        .join(".depot")
+        .join("vitest-results.json")
+}
+
+fn format_counts(counts: Counts) -> String {
+    let mut parts = vec![format!("{} passed", counts.passed)];
+    if counts.failed > 0 {
+        parts.push(format!("{} failed", counts.failed));
+    }
+    if counts.skipped > 0 {
+        parts.push(format!("{} skipped", counts.skipped));
+    }
+    parts.join(", ")
+}
+
+/// Renders the test counts of each package, followed by every failing test.
+///
+/// Packages in `no_tests` had no test files, and are listed so they don't look like they passed.
+pub fn format_summary(results: &[PackageReport], no_tests: &[PackageName]) -> String {
+    let mut rows = results
+        .iter()
+        .map(|result| (&result.name, format_counts(result.report.counts())))
+        .chain(no_tests.iter().map(|name| (name, String::from("no tests"))))
+        .collect::<Vec<_>>();
+    rows.sort_by_key(|(name, _)| *name);
+    let width = rows
+        .iter()
+        .map(|(name, _)| name.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    let mut out = String::from("Test results:\n");
+    for (name, counts) in rows {
+        writeln!(out, "  {:<width$}  {counts}", name.to_string()).unwrap();
+    }
+
+    let mut total = Counts::default();
+    for PackageReport { report, .. } in results {
+        let counts = report.counts();
+        total.passed += counts.passed;
+        total.failed += counts.failed;
+        total.skipped += counts.skipped;
+    }
+    if results.len() > 1 {
+        writeln!(out, "  {:<width$}  {}", "total", format_counts(total)).unwrap();
+    }
+
+    let failures = results
+        .iter()
+        .flat_map(|result| {
+            result
+                .report
+                .failures(&result.root)
+                .into_iter()
+                .map(move |failure| (&result.name, failure))
+        })
+        .collect::<Vec<_>>();
+    if !failures.is_empty() {
+        out.push_str("\nFailures:\n");
+        for (name, failure) in failures {
+            let location = match failure.line {
+                Some(line) => format!("{}:{line}", failure.file),
+                None => failure.file,
+            };
+            match failure.name {
+                Some(test) => writeln!(out, "  {name} {location} > {test}").unwrap(),
+                None => writeln!(out, "  {name} {location}").unwrap(),
+            }
+            let message = failure.message.lines().next().unwrap_or_default();
+            if !message.is_empty() {
+                writeln!(out, "    {message}").unwrap();
+            }
+        }
+    }
+
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const REPORT: &str = r#"{
+      "numTotalTests": 4,
+      "testResults": [
+        {
+          "name": "/ws/packages/foo/tests/add.test.ts",
+          "status": "failed",
+          "message": "",
+          "startTime": 1000,
+          "endTime": 1012,
+          "assertionResults": [
+            { "fullName": "add works", "status": "passed", "duration": 2, "failureMessages": [] },
+            {
+              "fullName": "add overflows",
+              "status": "failed",
+              "duration": 3,
+              "failureMessages": ["AssertionError: expected 3 to be 100\n    at tests/add.test.ts:5:20"],
+              "location": { "line": 5, "column": 1 }
+            },
+            { "fullName": "add later", "status": "todo", "failureMessages": [] }
+          ]
+        },
+        {
+          "name": "/ws/packages/foo/tests/broken.test.ts",
+          "status": "failed",
+          "message": "Failed to load url ./missing",
+          "assertionResults": []
+        }
+      ]
+    }"#;
+
+    #[test]
+    fn test_summary() {
+        let report: VitestReport = serde_json::from_str(REPORT).unwrap();
+        assert_eq!(
+            report.counts(),
+            Counts {
+                passed: 1,
+                failed: 2,
+                skipped: 1
+            }
+        );
+
+        let name = PackageName {
+            name: "foo".into(),
+            scope: None,
+        };
+        let no_tests = PackageName {
+            name: "bar".into(),
+            scope: None,
+        };
+        let summary = format_summary(
+            &[PackageReport {
+                name,
+                root: PathBuf::from("/ws/packages/foo"),
+                report,
+            }],
+            &[no_tests],
+        );
+        assert_eq!(
+            summary,
+            "Test results:
+  bar  no tests
+  foo  1 passed, 2 failed, 1 skipped
+
+Failures:
+  foo tests/add.test.ts:5 > add overflows
+    AssertionError: expected 3 to be 100
+  foo tests/broken.test.ts
+    Failed to load url ./missing"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use std::{collections::HashSet, time::Duration};
+
+use anyhow::{Result, bail};
+use notify::RecursiveMode;
+
+use super::Command;
+use crate::{
+    utils::FileWatcher,
+    workspace::{CommandRuntime, Workspace},
+};
+
+/// Re-run a command whenever the input files of its tasks change
+#[derive(clap::Parser, Debug)]
+pub struct WatchArgs {
+    /// Command to re-run, e.g. `fmt` or `task codegen`
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+    pub command: Vec<String>,
+}
+
+pub struct WatchCommand {
+    args: WatchArgs,
+}
+
+impl WatchCommand {
+    pub fn new(args: WatchArgs) -> Self {
+        WatchCommand { args }
+    }
+
+    pub async fn run(self, ws: &Workspace) -> Result<()> {
+        let root = Command::parse_args(self.args.command.clone())?.kind(ws)?;
+        if root.runtime() == Some(CommandRuntime::RunForever) {
+            bail!("`depot watch` cannot run a command that already runs forever");
+        }
+
+        let mut watcher = FileWatcher::new(Duration::from_millis(200))?;
+        let mut watched = HashSet::new();
+        let command_str = self.args.command.join(" ");
+
+        loop {
+            // Input files can be added between runs, so watch the directories that contain them.
+            for task in ws.task_graph(&root).nodes() {
+                let dirs = task
+                    .input_files()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|file| file.parent());
+                for dir in dirs {
+                    if watched.insert(dir.to_path_buf()) {
+                        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+                    }
+                }
+            }
+
+            ws.clear_processes();
+
+            // A change during a run cancels it, and the stale tasks are re-run right away.
+            let mut changed = false;
+            let cancel = async {
+                if watcher.next().await.is_some() {
+                    changed = true;
+                } else {
+                    std::future::pending::<()>().await;
+                }
+            };
+            if let Err(e) = ws.run_until(root.clone(), cancel).await {
+                eprintln!("{e:?}");
+            }
+
+            if !changed {
+                println!("Waiting for changes to re-run `depot {command_str}`...");
+                if watcher.next().await.is_none() {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
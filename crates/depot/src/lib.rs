@@ -11,10 +11,10 @@ use self::commands::Command;
 use anyhow::{Result, bail};
 use clap::Parser;
 use commands::{
-    build::BuildCommand, clean::CleanCommand, doc::DocCommand, fix::FixCommand, fmt::FmtCommand,
-    init::InitCommand, new::NewCommand, test::TestCommand,
+    env::EnvCommand, graph::GraphCommand, metadata::MetadataCommand, new::NewCommand,
+    watch::WatchCommand,
 };
-use workspace::{Workspace, package::PackageName};
+use workspace::{Workspace, package::PackageName, profile::RELEASE_PROFILE};
 
 mod commands;
 mod logger;
@@ -27,6 +27,10 @@ pub struct CommonArgs {
     #[clap(short, long)]
     package: Option<PackageName>,
 
+    /// Build profile to use, as declared under "profiles" in the workspace's depot config
+    #[clap(long)]
+    profile: Option<String>,
+
     /// Enable incremental compilation
     #[clap(long)]
     incremental: bool,
@@ -48,7 +52,10 @@ struct Args {
 
 #[allow(clippy::missing_errors_doc)]
 pub async fn run() -> Result<()> {
-    let Args { command, common } = Args::parse();
+    let Args {
+        command,
+        mut common,
+    } = Args::parse();
 
     if utils::find_node().is_none() {
         bail!(
@@ -67,18 +74,33 @@ pub async fn run() -> Result<()> {
         command => command,
     };
 
+    if let Command::Build(args) = &command
+        && args.release
+    {
+        if common
+            .profile
+            .as_deref()
+            .is_some_and(|p| p != RELEASE_PROFILE)
+        {
+            bail!("`--release` conflicts with `--profile`");
+        }
+        common.profile = Some(RELEASE_PROFILE.to_string());
+    }
+
+    if matches!(command, Command::Watch(..)) {
+        // `depot watch` relies on fingerprints to only re-run stale tasks.
+        common.incremental = true;
+    }
+
     let ws = Workspace::load(None, common).await?;
 
     // TODO: merge all tasks into a single task graph like Cargo
     let command = match command {
-        Command::Init(args) => InitCommand::new(args).kind(),
-        Command::Build(args) => BuildCommand::new(args).kind(),
-        Command::Test(args) => TestCommand::new(args).kind(),
-        Command::Fmt(args) => FmtCommand::new(args).kind(),
-        Command::Clean(args) => CleanCommand::new(args).kind(),
-        Command::Doc(args) => DocCommand::new(args).kind(),
-        Command::Fix(args) => FixCommand::new(args).kind(),
-        Command::New(..) => unreachable!(),
+        Command::Graph(args) => return GraphCommand::new(args).run(&ws),
+        Command::Metadata(args) => return MetadataCommand::new(args).run(&ws),
+        Command::Env(args) => return EnvCommand::new(args).run(&ws),
+        Command::Watch(args) => return WatchCommand::new(args).run(&ws).await,
+        command => command.kind(&ws)?,
     };
 
     ws.run(command).await?;
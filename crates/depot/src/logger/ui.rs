@@ -24,7 +24,20 @@ use std::{
 };
 use tokio::sync::Notify;
 
-use crate::workspace::{Workspace, process::Process};
+use crate::workspace::{Workspace, package::Package, process::Process};
+
+/// Describes the dependencies a package is waiting on in watch mode, if any.
+fn upstream_status(pkg: &Package) -> Option<String> {
+    let waiting_on = pkg.waiting_on();
+    (!waiting_on.is_empty()).then(|| {
+        let names = waiting_on
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("waiting for upstream {names}")
+    })
+}
 
 pub struct FullscreenRenderer {
     terminal: Mutex<Terminal>,
@@ -63,7 +76,10 @@ impl FullscreenRenderer {
                 .package_display_order()
                 .enumerate()
                 .map(|(i, pkg)| {
-                    let pkg_name = pkg.name.to_string();
+                    let pkg_name = match upstream_status(pkg) {
+                        Some(status) => format!("{} ({status})", pkg.name),
+                        None => pkg.name.to_string(),
+                    };
                     let mut style = Style::default();
                     if i == selected {
                         style = style.add_modifier(Modifier::BOLD);
@@ -294,7 +310,11 @@ impl InlineRenderer {
             }
 
             if ws.monorepo {
-                writeln!(&mut output, "{}", pkg.name)?;
+                write!(&mut output, "{}", pkg.name)?;
+                if let Some(status) = upstream_status(pkg) {
+                    meta!(" ({status})");
+                }
+                writeln!(&mut output)?;
             }
 
             for (j, process) in pkg_processes.iter().enumerate() {
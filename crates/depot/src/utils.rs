@@ -1,9 +1,14 @@
 use anyhow::{Context, Result};
+use log::warn;
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{DebounceEventResult, Debouncer};
+use tokio::sync::mpsc::UnboundedReceiver;
 
 use std::{
     fs,
     path::{Path, PathBuf},
     process::Command,
+    time::Duration,
 };
 
 pub fn create_dir(path: impl AsRef<Path>) -> Result<()> {
@@ -35,6 +40,32 @@ pub fn get_git_root(cwd: &Path) -> Option<PathBuf> {
         .then(|| PathBuf::from(String::from_utf8(output.stdout).unwrap().trim()))
 }
 
+/// Returns the full hash of the commit that `rev` (like `HEAD` or `main`) refers to.
+pub fn get_git_commit(cwd: &Path, rev: &str) -> Option<String> {
+    let mut cmd = Command::new("git");
+    cmd.args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{rev}^{{commit}}"))
+        .current_dir(cwd);
+    let output = cmd.output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8(output.stdout).unwrap().trim().to_string())
+}
+
+/// Returns the path to `to` relative to the directory `from`, using `/` as the separator.
+/// Both paths must be absolute.
+pub fn relative_path(from: &Path, to: &Path) -> String {
+    let from = from.components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let parents = from[common..].iter().map(|_| "..".to_string());
+    let rest = to[common..]
+        .iter()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned());
+    parents.chain(rest).collect::<Vec<_>>().join("/")
+}
+
 pub fn remove_dir_all_if_exists(dir: impl AsRef<Path>) -> Result<()> {
     let dir = dir.as_ref();
     if !dir.exists() {
@@ -112,3 +143,39 @@ pub fn find_pnpm(root: Option<&Path>) -> Option<PathBuf> {
         .filter(|root| root.exists());
     pnpm_in_root.or_else(|| pathsearch::find_executable_in_path("pnpm"))
 }
+
+/// Watches paths for changes, grouping bursts of events (e.g. from a save-all) into a single batch.
+pub struct FileWatcher {
+    debouncer: Debouncer<RecommendedWatcher>,
+    rx: UnboundedReceiver<DebounceEventResult>,
+}
+
+impl FileWatcher {
+    pub fn new(timeout: Duration) -> Result<Self> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let debouncer = notify_debouncer_mini::new_debouncer(timeout, None, move |events| {
+            let _ = tx.send(events);
+        })?;
+        Ok(FileWatcher { debouncer, rx })
+    }
+
+    pub fn watch(&mut self, path: &Path, mode: RecursiveMode) -> Result<()> {
+        self.debouncer
+            .watcher()
+            .watch(path, mode)
+            .with_context(|| format!("Failed to watch path: {}", path.display()))
+    }
+
+    /// Waits for the next batch of changed paths, or returns `None` if the watcher has stopped.
+    ///
+    /// Watcher errors are usually transient (e.g. a file deleted while being scanned),
+    /// so they are logged and skipped rather than returned.
+    pub async fn next(&mut self) -> Option<Vec<PathBuf>> {
+        loop {
+            match self.rx.recv().await? {
+                Ok(events) => return Some(events.into_iter().map(|event| event.path).collect()),
+                Err(e) => warn!("File watch errors: {e:?}"),
+            }
+        }
+    }
+}
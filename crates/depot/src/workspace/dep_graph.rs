@@ -1,15 +1,45 @@
 use anyhow::{Result, bail};
 use bimap::BiHashMap;
 use petgraph::{
-    graph::DiGraph,
+    graph::{DiGraph, EdgeIndex, EdgeReference},
     prelude::NodeIndex,
-    visit::{DfsPostOrder, Walker},
+    visit::{DfsPostOrder, EdgeFiltered, EdgeRef, Walker},
 };
-use std::hash::Hash;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Write,
+    hash::Hash,
+};
+
+/// Data attached to the edges of a [`DepGraph`].
+pub trait DepEdge: Clone + PartialEq {
+    /// Describes the edge in error messages, e.g. `"devDependencies"`.
+    fn describe(&self) -> Option<String> {
+        None
+    }
+
+    /// How preferable it is to remove this edge to break a cycle. Higher is more preferable.
+    fn break_priority(&self) -> u8 {
+        0
+    }
+
+    /// Whether the edge constrains the order in which nodes are processed.
+    ///
+    /// Non-ordering edges are allowed to form cycles, and are not followed by
+    /// [`DepGraph::immediate_deps_for`] or [`DepGraph::all_deps_for`].
+    fn is_ordering(&self) -> bool {
+        true
+    }
+}
+
+impl DepEdge for () {}
+
+/// A view of a [`DepGraph`] that only contains its ordering edges.
+type OrderingGraph<'a, E> = EdgeFiltered<&'a DiGraph<(), E>, fn(EdgeReference<'_, E>) -> bool>;
 
 /// Generic data structure for representing dependencies between objects.
-pub struct DepGraph<T> {
-    graph: DiGraph<(), ()>,
+pub struct DepGraph<T, E = ()> {
+    graph: DiGraph<(), E>,
     nodes: BiHashMap<T, NodeIndex>,
     roots: Vec<T>,
 }
@@ -23,11 +53,31 @@ impl<T: Hash + PartialEq + Eq + Clone> DepGraph<T> {
         roots: Vec<T>,
         stringify: impl Fn(&T) -> String,
         compute_deps: impl Fn(&T) -> Vec<T>,
+    ) -> Result<Self> {
+        Self::build_with_edges(roots, stringify, |el| {
+            compute_deps(el).into_iter().map(|dep| (dep, ())).collect()
+        })
+    }
+}
+
+impl<T: Hash + PartialEq + Eq + Clone, E: DepEdge> DepGraph<T, E> {
+    /// Like [`DepGraph::build`], except that `compute_deps` also returns data for each edge.
+    ///
+    /// Returns an error if the ordering edges of the graph contain a cycle. The error describes every
+    /// such cycle in the graph.
+    pub fn build_with_edges(
+        roots: Vec<T>,
+        stringify: impl Fn(&T) -> String,
+        compute_deps: impl Fn(&T) -> Vec<(T, E)>,
     ) -> Result<Self> {
         let mut graph = DiGraph::new();
         let mut nodes = BiHashMap::new();
         let mut stack = vec![];
 
+        let mut roots = roots;
+        let mut seen = HashSet::new();
+        roots.retain(|root| seen.insert(root.clone()));
+
         for root in &roots {
             let idx = graph.add_node(());
             nodes.insert(root.clone(), idx);
@@ -35,7 +85,7 @@ impl<T: Hash + PartialEq + Eq + Clone> DepGraph<T> {
         }
 
         while let Some((idx, el)) = stack.pop() {
-            for dep in compute_deps(&el) {
+            for (dep, edge) in compute_deps(&el) {
                 let dep_idx = match nodes.get_by_left(&dep) {
                     Some(dep_idx) => *dep_idx,
                     None => {
@@ -46,24 +96,154 @@ impl<T: Hash + PartialEq + Eq + Clone> DepGraph<T> {
                     }
                 };
 
-                graph.add_edge(idx, dep_idx, ());
+                let exists = graph
+                    .edges_connecting(idx, dep_idx)
+                    .any(|other| *other.weight() == edge);
+                if !exists {
+                    graph.add_edge(idx, dep_idx, edge);
+                }
+            }
+        }
+
+        let dg = DepGraph {
+            graph,
+            nodes,
+            roots,
+        };
+
+        let cycles = dg.find_cycles(&stringify);
+        if !cycles.is_empty() {
+            bail!("{}", dg.cycle_report(&cycles, &stringify));
+        }
+
+        Ok(dg)
+    }
+
+    /// Returns one cycle for each strongly connected component of the graph, where a cycle
+    /// is a sequence of edges that starts and ends at the same node.
+    fn find_cycles(&self, stringify: &impl Fn(&T) -> String) -> Vec<Cycle> {
+        let mut cycles = petgraph::algo::tarjan_scc(&self.ordering_graph())
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1
+                    || self
+                        .ordering_edges(component[0])
+                        .any(|e| e.target() == component[0])
+            })
+            .filter_map(|component| {
+                let members = component.iter().copied().collect::<HashSet<_>>();
+                let start = component
+                    .iter()
+                    .copied()
+                    .min_by_key(|idx| stringify(self.value(*idx)))?;
+                let edges = self.shortest_cycle(start, &members)?;
+                let mut members = component;
+                members.sort_by_key(|idx| stringify(self.value(*idx)));
+                Some(Cycle { members, edges })
+            })
+            .collect::<Vec<_>>();
+        cycles.sort_by_key(|cycle| stringify(self.value(cycle.members[0])));
+        cycles
+    }
+
+    /// Finds the shortest path from `start` back to itself that stays within `members`.
+    fn shortest_cycle(
+        &self,
+        start: NodeIndex,
+        members: &HashSet<NodeIndex>,
+    ) -> Option<Vec<EdgeIndex>> {
+        let mut parent: HashMap<NodeIndex, EdgeIndex> = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            for edge in self.ordering_edges(node) {
+                let next = edge.target();
+                if !members.contains(&next) {
+                    continue;
+                }
+
+                if next == start {
+                    let mut path = vec![edge.id()];
+                    let mut cur = node;
+                    while cur != start {
+                        let prev = parent[&cur];
+                        path.push(prev);
+                        cur = self.graph.edge_endpoints(prev).unwrap().0;
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+
+                if let std::collections::hash_map::Entry::Vacant(entry) = parent.entry(next) {
+                    entry.insert(edge.id());
+                    queue.push_back(next);
+                }
             }
         }
+        None
+    }
+
+    fn cycle_report(&self, cycles: &[Cycle], stringify: &impl Fn(&T) -> String) -> String {
+        let describe_edge = |edge: EdgeIndex| {
+            let (src, dst) = self.graph.edge_endpoints(edge).unwrap();
+            let mut s = format!(
+                "{} → {}",
+                stringify(self.value(src)),
+                stringify(self.value(dst))
+            );
+            if let Some(desc) = self.graph[edge].describe() {
+                write!(s, " ({desc})").unwrap();
+            }
+            s
+        };
+
+        let mut report = if cycles.len() == 1 {
+            String::from("Cycle detected in dependency graph:")
+        } else {
+            format!("{} cycles detected in dependency graph:", cycles.len())
+        };
+
+        for cycle in cycles {
+            let path = cycle
+                .edges
+                .iter()
+                .map(|edge| stringify(self.value(self.graph.edge_endpoints(*edge).unwrap().0)))
+                .chain([stringify(self.value(cycle.members[0]))])
+                .collect::<Vec<_>>()
+                .join(" → ");
+            write!(report, "\n\n  {path}").unwrap();
+
+            if cycle.members.len() > cycle.edges.len() {
+                let members = cycle
+                    .members
+                    .iter()
+                    .map(|idx| stringify(self.value(*idx)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(report, "\n  (part of a larger cycle involving: {members})").unwrap();
+            }
 
-        let sort = petgraph::algo::toposort(&graph, None);
-        match sort {
-            Ok(_) => Ok(DepGraph {
-                graph,
-                nodes,
-                roots,
-            }),
-            Err(cycle) => {
-                bail!(
-                    "Cycle detected in dependency graph involving node: {}",
-                    stringify(nodes.get_by_right(&cycle.node_id()).unwrap())
-                )
+            if self.graph[cycle.edges[0]].describe().is_some() {
+                for edge in &cycle.edges {
+                    write!(report, "\n    {}", describe_edge(*edge)).unwrap();
+                }
             }
+
+            // Prefer the highest-priority edge, and otherwise the edge that closes the cycle.
+            let to_break = cycle
+                .edges
+                .iter()
+                .copied()
+                .max_by_key(|edge| self.graph[*edge].break_priority())
+                .unwrap();
+            write!(
+                report,
+                "\n  hint: consider removing the dependency {}",
+                describe_edge(to_break)
+            )
+            .unwrap();
         }
+
+        report
     }
 
     fn index(&self, el: &T) -> NodeIndex {
@@ -82,23 +262,63 @@ impl<T: Hash + PartialEq + Eq + Clone> DepGraph<T> {
         self.all_deps_for(el).any(|dep2| dep == dep2)
     }
 
+    fn ordering_graph(&self) -> OrderingGraph<'_, E> {
+        fn is_ordering<E: DepEdge>(edge: EdgeReference<'_, E>) -> bool {
+            edge.weight().is_ordering()
+        }
+        EdgeFiltered(&self.graph, is_ordering)
+    }
+
+    fn ordering_edges(&self, index: NodeIndex) -> impl Iterator<Item = EdgeReference<'_, E>> {
+        self.graph
+            .edges(index)
+            .filter(|edge| edge.weight().is_ordering())
+    }
+
     pub fn immediate_deps_for<'a>(&'a self, el: &T) -> impl Iterator<Item = &'a T> + 'a {
+        self.ordering_edges(self.index(el))
+            .map(|edge| self.value(edge.target()))
+    }
+
+    /// Returns the immediate dependencies of `el` along every edge, including non-ordering edges.
+    pub fn immediate_edges_for<'a>(&'a self, el: &T) -> impl Iterator<Item = (&'a T, &'a E)> + 'a {
         self.graph
-            .neighbors_directed(self.index(el), petgraph::Direction::Outgoing)
-            .map(|node| self.value(node))
+            .edges(self.index(el))
+            .map(|edge| (self.value(edge.target()), edge.weight()))
     }
 
     pub fn all_deps_for<'a>(&'a self, el: &T) -> impl Iterator<Item = &'a T> + 'a {
         let index = self.index(el);
-        DfsPostOrder::new(&self.graph, index)
-            .iter(&self.graph)
-            .filter(move |dep| *dep != index)
-            .map(|idx| self.value(idx))
+        let graph = self.ordering_graph();
+        let deps = DfsPostOrder::new(&graph, index)
+            .iter(&graph)
+            .filter(|dep| *dep != index)
+            .collect::<Vec<_>>();
+        deps.into_iter().map(|idx| self.value(idx))
     }
 
     pub fn roots(&self) -> impl Iterator<Item = &T> {
         self.roots.iter()
     }
+
+    /// Returns every edge as a `(dependent, dependency, data)` triple.
+    pub fn edges(&self) -> impl Iterator<Item = (&T, &T, &E)> {
+        self.graph.raw_edges().iter().map(|edge| {
+            (
+                self.value(edge.source()),
+                self.value(edge.target()),
+                &edge.weight,
+            )
+        })
+    }
+}
+
+/// A cycle within a strongly connected component of a [`DepGraph`].
+struct Cycle {
+    /// All nodes of the component, sorted by name.
+    members: Vec<NodeIndex>,
+    /// The edges of the cycle, starting and ending at `members[0]`.
+    edges: Vec<EdgeIndex>,
 }
 
 #[cfg(test)]
@@ -147,7 +367,14 @@ mod test {
         assert_eq!(
             dg.roots().copied().collect::<HashSet<_>>(),
             hashset! { 0, 1 }
-        )
+        );
+
+        assert_eq!(
+            dg.edges()
+                .map(|(a, b, ())| (*a, *b))
+                .collect::<HashSet<_>>(),
+            hashset! { (0, 2), (1, 2), (2, 3) }
+        );
     }
 
     #[test]
@@ -162,5 +389,23 @@ mod test {
             },
         );
         assert!(dg.is_err());
+
+        let Err(err) = DepGraph::build(vec![0], ToString::to_string, |i| match i {
+            0 => vec![1],
+            1 => vec![2, 3],
+            2 => vec![0],
+            3 => vec![1, 3],
+            _ => unreachable!(),
+        }) else {
+            panic!("expected a cycle")
+        };
+        assert_eq!(
+            err.to_string(),
+            "Cycle detected in dependency graph:
+
+  0 → 1 → 2 → 0
+  (part of a larger cycle involving: 0, 1, 2, 3)
+  hint: consider removing the dependency 2 → 0"
+        );
     }
 }
@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use std::{fs, path::Path};
+
+use super::profile::Profile;
+
+/// Variables loaded from `.env` files.
+pub type EnvVars = IndexMap<String, String>;
+
+/// Loads the `.env` files in `dir` for the given `profile`.
+///
+/// Later files take precedence: `.env`, `.env.local`, `.env.[mode]`, `.env.[mode].local`,
+/// `.env.[profile]`, `.env.[profile].local`. The files of the profile's Vite mode are only
+/// loaded if the mode is named differently from the profile.
+/// Variables already set in depot's own environment are left out, since they take precedence
+/// over every `.env` file.
+pub fn load_env_files(dir: &Path, profile: &Profile) -> Result<EnvVars> {
+    let mut file_names = vec![".env".to_string(), ".env.local".to_string()];
+    let mut suffixes = vec![&profile.name];
+    if profile.mode != profile.name {
+        suffixes.insert(0, &profile.mode);
+    }
+    for suffix in suffixes {
+        file_names.push(format!(".env.{suffix}"));
+        file_names.push(format!(".env.{suffix}.local"));
+    }
+
+    let mut vars = EnvVars::new();
+    for file_name in file_names {
+        let path = dir.join(file_name);
+        if !path.exists() {
+            continue;
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read env file: {}", path.display()))?;
+        vars.extend(parse_env(&contents));
+    }
+
+    vars.retain(|key, _| std::env::var_os(key).is_none());
+    Ok(vars)
+}
+
+/// Parses the contents of a `.env` file, supporting comments, `export` prefixes and quoted values.
+fn parse_env(contents: &str) -> EnvVars {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = if let Some(quoted) = unquote(value, '"') {
+                quoted.replace("\\n", "\n")
+            } else if let Some(quoted) = unquote(value, '\'') {
+                quoted.to_string()
+            } else {
+                match value.split_once(" #") {
+                    Some((value, _comment)) => value.trim_end().to_string(),
+                    None => value.to_string(),
+                }
+            };
+            Some((key.trim().to_string(), value))
+        })
+        .collect()
+}
+
+fn unquote(value: &str, quote: char) -> Option<&str> {
+    value.strip_prefix(quote)?.strip_suffix(quote)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_env() {
+        let vars = parse_env(
+            r#"
+# comment
+VITE_API_URL=https://example.com # trailing comment
+export SECRET = 'a # b'
+GREETING="hello\nworld"
+EMPTY=
+not a var
+"#,
+        );
+        assert_eq!(vars["VITE_API_URL"], "https://example.com");
+        assert_eq!(vars["SECRET"], "a # b");
+        assert_eq!(vars["GREETING"], "hello\nworld");
+        assert_eq!(vars["EMPTY"], "");
+        assert_eq!(vars.len(), 4);
+    }
+}
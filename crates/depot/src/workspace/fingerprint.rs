@@ -16,12 +16,19 @@ use crate::utils;
 #[derive(Serialize, Deserialize, PartialEq, Eq)]
 pub struct Fingerprints {
     fingerprints: HashMap<String, SystemTime>,
+
+    /// Hashes of the outputs that dependents rely on, see [`PackageCommand::interface_hash`].
+    ///
+    /// [`PackageCommand::interface_hash`]: super::PackageCommand::interface_hash
+    #[serde(default)]
+    interface_digests: HashMap<String, String>,
 }
 
 impl Fingerprints {
     pub fn new() -> Self {
         Fingerprints {
             fingerprints: HashMap::new(),
+            interface_digests: HashMap::new(),
         }
     }
 
@@ -49,6 +56,11 @@ impl Fingerprints {
         self.fingerprints.insert(key, SystemTime::now());
     }
 
+    /// Records the interface hash for `key`, returning true if it is the same as the previous one.
+    pub fn update_interface(&mut self, key: String, hash: String) -> bool {
+        self.interface_digests.insert(key, hash.clone()) == Some(hash)
+    }
+
     fn file_path(root: &Path) -> PathBuf {
         root.join("node_modules").join(".depot-fingerprints.json")
     }
@@ -96,6 +108,9 @@ mod test {
         fingerprints.update_time("file.txt".into());
         assert!(fingerprints.can_skip("file.txt", vec![file.clone()]));
 
+        assert!(!fingerprints.update_interface("file.txt".into(), "a".into()));
+        assert!(fingerprints.update_interface("file.txt".into(), "a".into()));
+
         fs::write(&file, "World")?;
         assert!(!fingerprints.can_skip("file.txt", vec![file.clone()]));
 
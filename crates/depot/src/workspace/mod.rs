@@ -1,8 +1,10 @@
 use self::{
     dep_graph::DepGraph,
+    dotenv::EnvVars,
     fingerprint::Fingerprints,
-    package::{PackageGraph, PackageIndex},
+    package::{CoverageThresholds, PackageGraph, PackageIndex},
     process::Process,
+    profile::{DEV_PROFILE, Profile, ProfileConfig},
 };
 use crate::{CommonArgs, shareable, utils};
 
@@ -11,6 +13,7 @@ use futures::{
     StreamExt,
     stream::{self, TryStreamExt},
 };
+use indexmap::IndexMap;
 use log::{debug, warn};
 use manifest::DepotManifest;
 use package::Package;
@@ -23,17 +26,30 @@ use std::{
     sync::{Arc, RwLock, RwLockReadGuard},
 };
 
-mod dep_graph;
+pub mod dep_graph;
+pub mod dotenv;
 mod fingerprint;
 mod manifest;
 pub mod package;
 pub mod process;
-mod runner;
+pub mod profile;
+pub mod runner;
 
 #[derive(serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct WorkspaceDepotConfig {
     pub depot_version: String,
+
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub profiles: IndexMap<String, ProfileConfig>,
+
+    /// Type check all packages with one `tsc --build`, keeping tsconfig references in sync.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub project_references: bool,
+
+    /// Minimum test coverage of the whole workspace, checked by `depot test --coverage`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage_thresholds: Option<CoverageThresholds>,
 }
 
 pub type WorkspaceManifest = DepotManifest<WorkspaceDepotConfig>;
@@ -58,7 +74,15 @@ pub struct WorkspaceInner {
     /// CLI arguments that apply to the whole workspace.
     pub common: CommonArgs,
 
+    /// The packages selected to run commands on, which is every package unless `--package` is given.
     roots: Vec<Package>,
+
+    /// The build profile selected with `--profile`.
+    pub profile: Profile,
+
+    /// Settings from the "depot" entry of the workspace's `package.json`.
+    pub config: WorkspaceDepotConfig,
+
     package_display_order: Vec<PackageIndex>,
     processes: RwLock<Vec<Arc<Process>>>,
     fingerprints: RwLock<Fingerprints>,
@@ -103,6 +127,41 @@ impl CommandInner {
         }
     }
 
+    pub fn finish(&self, ws: &Workspace) -> Result<()> {
+        match self {
+            CommandInner::Package(cmd) => cmd.finish(ws),
+            CommandInner::Workspace(cmd) => cmd.finish(ws),
+        }
+    }
+
+    pub fn summary(&self) -> Option<String> {
+        match self {
+            CommandInner::Package(cmd) => cmd.summary(),
+            CommandInner::Workspace(cmd) => cmd.summary(),
+        }
+    }
+
+    pub fn interface_hash(&self, package: &Package) -> Option<String> {
+        match self {
+            CommandInner::Package(cmd) => cmd.interface_hash(package),
+            CommandInner::Workspace(_) => None,
+        }
+    }
+
+    pub fn uses_dep_interfaces(&self, package: &Package) -> bool {
+        match self {
+            CommandInner::Package(cmd) => cmd.uses_dep_interfaces(package),
+            CommandInner::Workspace(_) => false,
+        }
+    }
+
+    pub fn uses_dev_deps(&self) -> bool {
+        match self {
+            CommandInner::Package(cmd) => cmd.uses_dev_deps(),
+            CommandInner::Workspace(_) => false,
+        }
+    }
+
     pub fn deps(&self) -> Vec<Command> {
         match self {
             CommandInner::Package(cmd) => cmd.deps(),
@@ -157,10 +216,22 @@ impl Command {
 
 pub trait CoreCommand {
     fn name(&self) -> String;
+
+    /// Runs once every task of the command has finished successfully, before the summary is printed.
+    fn finish(&self, _ws: &Workspace) -> Result<()> {
+        Ok(())
+    }
+
+    /// A message printed once the command has finished running, if any.
+    fn summary(&self) -> Option<String> {
+        None
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum CommandRuntime {
+    #[default]
     WaitForDependencies,
     RunImmediately,
     RunForever,
@@ -181,6 +252,30 @@ pub trait PackageCommand: CoreCommand + Debug + Send + Sync + 'static {
     fn runtime(&self) -> CommandRuntime {
         CommandRuntime::RunImmediately
     }
+
+    /// Files that determine whether the command needs to be re-run on `package`,
+    /// or `None` if it must always run.
+    fn input_files(&self, package: &Package) -> Option<Vec<PathBuf>> {
+        Some(package.all_files().collect())
+    }
+
+    /// A hash of the outputs of `package` that dependents rely on, like the declarations emitted
+    /// by a library, or `None` if dependents may rely on any of its outputs.
+    fn interface_hash(&self, _package: &Package) -> Option<String> {
+        None
+    }
+
+    /// Returns true if the task for `package` only relies on the [`interface_hash`](Self::interface_hash)
+    /// of its dependencies, so it can stay skipped when a dependency re-runs without changing it.
+    fn uses_dep_interfaces(&self, _package: &Package) -> bool {
+        false
+    }
+
+    /// Returns true if the command imports the dev dependencies of a package, like tests do.
+    /// The dependencies of such a command then also run on those dev dependencies.
+    fn uses_dev_deps(&self) -> bool {
+        false
+    }
 }
 
 #[async_trait::async_trait]
@@ -224,6 +319,10 @@ Double-check that this workspace is compatible and update depot.depot_version in
       );
         }
 
+        let profile_name = common.profile.as_deref().unwrap_or(DEV_PROFILE);
+        let profile = Profile::resolve(profile_name, &manifest.config.profiles)?;
+        debug!("Build profile: {profile:?}");
+
         let pkg_roots = if monorepo {
             pkg_dir
                 .read_dir()?
@@ -288,6 +387,8 @@ Double-check that this workspace is compatible and update depot.depot_version in
             pkg_graph,
             common,
             roots,
+            profile,
+            config: manifest.config,
             processes: RwLock::default(),
             fingerprints,
         });
@@ -307,24 +408,36 @@ impl WorkspaceInner {
             .map(|idx| &self.packages[*idx])
     }
 
-    pub fn start_process(
-        &self,
-        script: &'static str,
-        configure: impl FnOnce(&mut tokio::process::Command),
-    ) -> Result<Arc<Process>> {
-        log::trace!("Starting process: {script}");
+    /// Variables from the `.env` files in the workspace root for the selected profile.
+    pub fn env(&self) -> Result<EnvVars> {
+        dotenv::load_env_files(&self.root, &self.profile)
+    }
 
+    /// Creates a command that runs `script` through pnpm from the workspace root.
+    pub fn command(&self, script: &str) -> Result<tokio::process::Command> {
         let pnpm = utils::find_pnpm(Some(&self.root))
             .ok_or(anyhow!("could not find pnpm on your system"))?;
 
         let mut cmd = tokio::process::Command::new(pnpm);
         cmd.current_dir(&self.root);
         cmd.env("NODE_PATH", self.root.join("node_modules"));
+        cmd.envs(self.env()?);
 
         if script != "pnpm" {
             cmd.args(["exec", script]);
         }
 
+        Ok(cmd)
+    }
+
+    pub fn start_process(
+        &self,
+        script: &str,
+        configure: impl FnOnce(&mut tokio::process::Command),
+    ) -> Result<Arc<Process>> {
+        log::trace!("Starting process: {script}");
+
+        let mut cmd = self.command(script)?;
         configure(&mut cmd);
 
         Ok(Arc::new(Process::new(script.to_owned(), cmd)?))
@@ -332,7 +445,7 @@ impl WorkspaceInner {
 
     pub async fn exec(
         &self,
-        script: &'static str,
+        script: &str,
         configure: impl FnOnce(&mut tokio::process::Command),
     ) -> Result<()> {
         let process = self.start_process(script, configure)?;
@@ -344,9 +457,22 @@ impl WorkspaceInner {
         self.processes.read().unwrap()
     }
 
+    /// Removes the processes of previous runs so that renderers only show the current run.
+    pub fn clear_processes(&self) {
+        self.processes.write().unwrap().clear();
+        for pkg in &self.packages {
+            pkg.clear_processes();
+        }
+    }
+
     pub fn all_files(&self) -> impl Iterator<Item = PathBuf> + '_ {
         self.packages.iter().flat_map(|pkg| pkg.all_files())
     }
+
+    /// Returns true if the task with `key` last ran after all of `files` were modified.
+    pub fn is_fresh(&self, key: &str, files: impl IntoIterator<Item = PathBuf>) -> bool {
+        self.fingerprints.read().unwrap().can_skip(key, files)
+    }
 }
 
 pub type CommandGraph = DepGraph<Command>;
@@ -1,19 +1,27 @@
 use anyhow::{Context, Error, Result, bail, ensure};
 
-use ignore::Walk;
+use globset::{Glob, GlobSetBuilder};
+use ignore::{Walk, WalkBuilder};
+use indexmap::IndexMap;
 use maplit::hashset;
 use std::{
-    collections::HashSet,
+    collections::{BTreeSet, HashSet},
     fmt::{self, Debug},
     hash::Hash,
     path::{Path, PathBuf},
     str::FromStr,
     sync::{Arc, OnceLock, RwLock, RwLockReadGuard},
 };
+use tokio::sync::watch;
 
 use crate::{shareable, workspace::process::Process};
 
-use super::{Workspace, dep_graph::DepGraph, manifest::DepotManifest};
+use super::{
+    CommandRuntime, Workspace,
+    dep_graph::{DepEdge, DepGraph},
+    dotenv::{self, EnvVars},
+    manifest::DepotManifest,
+};
 
 #[derive(Copy, Clone, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
 pub enum Platform {
@@ -34,6 +42,15 @@ impl Platform {
     }
 }
 
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Platform::Browser => write!(f, "browser"),
+            Platform::Node => write!(f, "node"),
+        }
+    }
+}
+
 #[derive(Copy, Clone, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
 pub enum Target {
     #[serde(rename = "lib")]
@@ -58,6 +75,63 @@ impl Target {
     }
 }
 
+/// A module format emitted for library packages.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LibFormat {
+    Esm,
+    Cjs,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Lib => write!(f, "lib"),
+            Target::Site => write!(f, "site"),
+            Target::Script => write!(f, "script"),
+        }
+    }
+}
+
+/// The section of `package.json` that a dependency is declared in.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyKind {
+    Runtime,
+    Dev,
+    Peer,
+}
+
+impl DependencyKind {
+    /// The name of the `package.json` key for this kind of dependency.
+    pub fn manifest_key(self) -> &'static str {
+        match self {
+            DependencyKind::Runtime => "dependencies",
+            DependencyKind::Dev => "devDependencies",
+            DependencyKind::Peer => "peerDependencies",
+        }
+    }
+}
+
+impl DepEdge for DependencyKind {
+    fn describe(&self) -> Option<String> {
+        Some(self.manifest_key().to_string())
+    }
+
+    fn break_priority(&self) -> u8 {
+        match self {
+            DependencyKind::Peer => 1,
+            DependencyKind::Runtime | DependencyKind::Dev => 0,
+        }
+    }
+
+    /// Dev dependencies are only needed to test a package, not to build it, so they
+    /// are allowed to form cycles like in Cargo.
+    fn is_ordering(&self) -> bool {
+        !matches!(self, DependencyKind::Dev)
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Ord, PartialOrd)]
 pub struct PackageName {
     pub name: String,
@@ -124,6 +198,31 @@ pub struct PackageDepotConfig {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_extensions: Option<Vec<String>>,
+
+    /// Entry points of a library relative to `src`, e.g. `["lib.ts", "cli.ts"]`.
+    /// The first one is exported as the package's main module.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry_points: Option<Vec<String>>,
+
+    /// Module formats emitted for a library. Defaults to `["esm"]`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formats: Option<Vec<LibFormat>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tasks: Option<IndexMap<String, TaskConfig>>,
+
+    /// Budgets for the gzipped size of build outputs, e.g. `{"dist/*.js": "150kB"}`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_limit: Option<IndexMap<String, String>>,
+
+    /// Minimum test coverage of the package, checked by `depot test --coverage`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage_thresholds: Option<CoverageThresholds>,
+
+    /// Globs of test files relative to the package root, used to find which packages have tests.
+    /// Defaults to `["tests/**", "src/**/*.{test,spec}.{ts,tsx}"]`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_files: Option<Vec<String>>,
 }
 
 impl Default for PackageDepotConfig {
@@ -134,10 +233,56 @@ impl Default for PackageDepotConfig {
             no_server: None,
             asset_extensions: None,
             source_extensions: None,
+            entry_points: None,
+            formats: None,
+            tasks: None,
+            size_limit: None,
+            coverage_thresholds: None,
+            test_files: None,
         }
     }
 }
 
+/// Minimum coverage percentages, e.g. `{"lines": 80, "branches": 70}`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Default, Debug)]
+pub struct CoverageThresholds {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statements: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branches: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub functions: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<f64>,
+}
+
+/// A user-defined task that can be run with `depot task <name>`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaskConfig {
+    /// Command line to run in the package root, e.g. `"graphql-codegen --watch"`.
+    pub command: String,
+
+    /// Tasks or built-in commands (like `"build"`) that must finish before this task runs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deps: Vec<String>,
+
+    /// Globs relative to the package root for files read by the task.
+    /// Defaults to every file in `src`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+
+    /// Globs relative to the package root for files generated by the task.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<String>,
+
+    #[serde(default)]
+    pub runtime: CommandRuntime,
+}
+
 pub type PackageManifest = DepotManifest<PackageDepotConfig>;
 
 pub type PackageIndex = usize;
@@ -146,7 +291,6 @@ pub struct PackageInner {
     // Metadata
     pub root: PathBuf,
     pub manifest: PackageManifest,
-    #[allow(unused)]
     pub platform: Platform,
     pub target: Target,
     pub name: PackageName,
@@ -155,6 +299,41 @@ pub struct PackageInner {
     // Internals
     ws: OnceLock<Workspace>,
     processes: RwLock<Vec<Arc<Process>>>,
+
+    // Watch-mode coordination
+    waiting_on: watch::Sender<BTreeSet<PackageName>>,
+    outputs_generation: watch::Sender<u64>,
+    outputs_ready: watch::Sender<bool>,
+}
+
+/// Notifies a package in watch mode when the outputs of its dependencies have been re-emitted.
+pub struct UpstreamOutputs {
+    outputs: Vec<watch::Receiver<u64>>,
+    waiting_on: watch::Receiver<BTreeSet<PackageName>>,
+    libs_ready: Vec<watch::Receiver<bool>>,
+}
+
+impl UpstreamOutputs {
+    /// Resolves once every library dependency has settled the outputs of its first build.
+    /// Those outputs then don't count as a change.
+    pub async fn ready(&mut self) {
+        for ready in &mut self.libs_ready {
+            let _ = ready.wait_for(|ready| *ready).await;
+        }
+        for outputs in &mut self.outputs {
+            outputs.borrow_and_update();
+        }
+    }
+
+    /// Resolves once the outputs of a dependency have changed and no dependency is still rebuilding.
+    pub async fn changed(&mut self) {
+        if self.outputs.is_empty() {
+            return std::future::pending().await;
+        }
+        let changes = self.outputs.iter_mut().map(|rx| Box::pin(rx.changed()));
+        let _ = futures::future::select_all(changes).await;
+        let _ = self.waiting_on.wait_for(BTreeSet::is_empty).await;
+    }
 }
 
 shareable!(Package, PackageInner);
@@ -171,6 +350,69 @@ impl Package {
         self.processes.read().unwrap()
     }
 
+    pub fn add_process(&self, process: Arc<Process>) {
+        self.processes.write().unwrap().push(process);
+    }
+
+    pub(super) fn clear_processes(&self) {
+        self.processes.write().unwrap().clear();
+    }
+
+    /// Returns the dependencies whose outputs are currently being rebuilt.
+    pub fn waiting_on(&self) -> Vec<PackageName> {
+        self.waiting_on.borrow().iter().cloned().collect()
+    }
+
+    fn dependents(&self) -> impl Iterator<Item = &Package> {
+        let pkg_graph = &self.workspace().pkg_graph;
+        pkg_graph
+            .nodes()
+            .filter(move |other| pkg_graph.is_dependent_on(other, self))
+    }
+
+    /// Marks the package's outputs as being rebuilt, so that dependents wait for them to settle.
+    pub fn mark_outputs_stale(&self) {
+        for dependent in self.dependents() {
+            dependent.waiting_on.send_modify(|names| {
+                names.insert(self.name.clone());
+            });
+        }
+    }
+
+    /// Marks the package's outputs as settled, notifying dependents if they `changed`.
+    pub fn mark_outputs_fresh(&self, changed: bool) {
+        self.outputs_ready
+            .send_if_modified(|ready| !std::mem::replace(ready, true));
+        for dependent in self.dependents() {
+            dependent
+                .waiting_on
+                .send_if_modified(|names| names.remove(&self.name));
+        }
+        if changed {
+            self.outputs_generation
+                .send_modify(|generation| *generation += 1);
+        }
+    }
+
+    pub fn upstream_outputs(&self) -> UpstreamOutputs {
+        UpstreamOutputs {
+            outputs: self
+                .workspace()
+                .pkg_graph
+                .all_deps_for(self)
+                .map(|dep| dep.outputs_generation.subscribe())
+                .collect(),
+            waiting_on: self.waiting_on.subscribe(),
+            libs_ready: self
+                .workspace()
+                .pkg_graph
+                .all_deps_for(self)
+                .filter(|dep| dep.target.is_lib())
+                .map(|dep| dep.outputs_ready.subscribe())
+                .collect(),
+        }
+    }
+
     pub fn from_parts(
         root: PathBuf,
         manifest: PackageManifest,
@@ -194,6 +436,9 @@ impl Package {
             index,
             ws: OnceLock::default(),
             processes: RwLock::default(),
+            waiting_on: watch::Sender::new(BTreeSet::new()),
+            outputs_generation: watch::Sender::new(0),
+            outputs_ready: watch::Sender::new(false),
         }))
     }
 
@@ -224,43 +469,140 @@ impl Package {
         Self::from_parts(root, manifest, index, target)
     }
 
-    pub fn start_process(
+    /// Variables from the `.env` files in the workspace and package roots,
+    /// with package values taking precedence.
+    pub fn env(&self) -> Result<EnvVars> {
+        let ws = self.workspace();
+        let mut vars = ws.env()?;
+        if self.root != ws.root {
+            vars.extend(dotenv::load_env_files(&self.root, &ws.profile)?);
+        }
+        Ok(vars)
+    }
+
+    fn spawn_process(
         &self,
-        script: &'static str,
+        script: &str,
         configure: impl FnOnce(&mut tokio::process::Command),
     ) -> Result<Arc<Process>> {
-        let process = self.workspace().start_process(script, |cmd| {
+        let env = self.env()?;
+        self.workspace().start_process(script, |cmd| {
             cmd.current_dir(&self.root);
+            cmd.envs(env);
             configure(cmd);
-        })?;
+        })
+    }
+
+    pub fn start_process(
+        &self,
+        script: &str,
+        configure: impl FnOnce(&mut tokio::process::Command),
+    ) -> Result<Arc<Process>> {
+        let process = self.spawn_process(script, configure)?;
         self.processes.write().unwrap().push(process.clone());
         Ok(process)
     }
 
+    /// Starts a new process that takes the place of `old` in the package's list of processes,
+    /// so that renderers show its output in the same pane.
+    pub fn replace_process(
+        &self,
+        old: &Arc<Process>,
+        script: &str,
+        configure: impl FnOnce(&mut tokio::process::Command),
+    ) -> Result<Arc<Process>> {
+        let process = self.spawn_process(script, configure)?;
+        let mut processes = self.processes.write().unwrap();
+        match processes.iter().position(|p| Arc::ptr_eq(p, old)) {
+            Some(i) => processes[i] = process.clone(),
+            None => processes.push(process.clone()),
+        }
+        Ok(process)
+    }
+
     pub async fn exec(
         &self,
-        script: &'static str,
+        script: &str,
         configure: impl FnOnce(&mut tokio::process::Command),
     ) -> Result<()> {
         self.start_process(script, configure)?
             .wait_for_success()
             .await
     }
+
+    /// Like [`Package::exec`], but restarts the process whenever the outputs of a dependency
+    /// change and have settled. Used for watchers that would otherwise see stale or
+    /// half-written outputs of the packages they depend on.
+    pub async fn exec_restarting(
+        &self,
+        script: &str,
+        configure: impl Fn(&mut tokio::process::Command),
+    ) -> Result<()> {
+        let mut upstream = self.upstream_outputs();
+        let mut process = self.start_process(script, &configure)?;
+        loop {
+            tokio::select! {
+              result = process.wait_for_success() => break result,
+              () = upstream.changed() => {
+                process = self.replace_process(&process, script, &configure)?;
+              }
+            }
+        }
+    }
 }
 
 impl PackageInner {
-    pub fn all_dependencies(&self) -> impl Iterator<Item = PackageName> + '_ {
+    /// Returns every dependency declared in the manifest along with its kind and version requirement.
+    pub fn dependencies(&self) -> impl Iterator<Item = (PackageName, DependencyKind, &str)> + '_ {
         let manifest = &self.manifest.manifest;
         let manifest_deps = [
-            &manifest.dependencies,
-            &manifest.dev_dependencies,
-            &manifest.peer_dependencies,
+            (&manifest.dependencies, DependencyKind::Runtime),
+            (&manifest.dev_dependencies, DependencyKind::Dev),
+            (&manifest.peer_dependencies, DependencyKind::Peer),
         ];
         manifest_deps
             .into_iter()
-            .flatten()
-            .flat_map(|deps| deps.keys())
-            .filter_map(|s| PackageName::from_str(s).ok())
+            .filter_map(|(deps, kind)| Some((deps.as_ref()?, kind)))
+            .flat_map(|(deps, kind)| {
+                deps.iter().filter_map(move |(name, version)| {
+                    let name = PackageName::from_str(name).ok()?;
+                    Some((name, kind, version.as_str()))
+                })
+            })
+    }
+
+    /// Returns the files that serve as the entry points for the package's target.
+    pub fn entry_points(&self) -> Vec<PathBuf> {
+        if let (Target::Lib, Some(entry_points)) = (self.target, &self.manifest.config.entry_points)
+        {
+            let src_dir = self.root.join("src");
+            return entry_points
+                .iter()
+                .map(|entry| src_dir.join(entry))
+                .collect();
+        }
+
+        let base = match self.target {
+            Target::Lib => "lib",
+            Target::Script => "main",
+            Target::Site => "index",
+        };
+        let mut entry_points = Package::find_source_file(&self.root, base)
+            .into_iter()
+            .collect::<Vec<_>>();
+        let index_html = self.root.join("index.html");
+        if self.target.is_site() && index_html.exists() {
+            entry_points.push(index_html);
+        }
+        entry_points
+    }
+
+    /// Returns the module formats emitted for a library package.
+    pub fn lib_formats(&self) -> Vec<LibFormat> {
+        match &self.manifest.config.formats {
+            Some(formats) => formats.clone(),
+            None => vec![LibFormat::Esm],
+        }
     }
 
     pub fn workspace(&self) -> &Workspace {
@@ -284,13 +626,28 @@ impl PackageInner {
         })
     }
 
-    pub fn asset_files(&self) -> impl Iterator<Item = PathBuf> + '_ {
+    fn asset_extensions(&self) -> HashSet<&str> {
         let mut asset_extensions: HashSet<&str> =
             hashset! { "scss", "css", "jpeg", "jpg", "png", "svg" };
         if let Some(exts) = &self.manifest.config.asset_extensions {
             asset_extensions.extend(exts.iter().map(String::as_str));
         }
+        asset_extensions
+    }
+
+    /// Returns true if `path` would be one of the [`asset_files`](Self::asset_files),
+    /// regardless of whether it currently exists.
+    pub fn is_asset_file(&self, path: &Path) -> bool {
+        let src_dir = self.root.join("src");
+        if path.starts_with(src_dir.join("assets")) {
+            return true;
+        }
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        path.starts_with(&src_dir) && ext.is_some_and(|ext| self.asset_extensions().contains(ext))
+    }
 
+    pub fn asset_files(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        let asset_extensions = self.asset_extensions();
         self.iter_files("src")
             .filter_map(move |path| {
                 let ext = path.extension()?;
@@ -319,7 +676,7 @@ impl PackageInner {
         ["src", "tests"]
             .into_iter()
             .flat_map(|dir| self.iter_files(dir))
-            .chain(CONFIG_FILES.iter().map(PathBuf::from))
+            .chain(CONFIG_FILES.iter().map(|file| self.root.join(file)))
             .filter_map(move |path| {
                 if !path.exists() {
                     return None;
@@ -334,6 +691,47 @@ impl PackageInner {
     pub fn all_files(&self) -> impl Iterator<Item = PathBuf> + '_ {
         self.iter_files("src")
     }
+
+    /// Returns every file in the package matching one of the `globs`, which are relative
+    /// to the package root. Unlike other file iterators, this includes ignored files
+    /// (such as build outputs) except for those in `node_modules`.
+    pub fn glob_files(&self, globs: &[String]) -> Result<Vec<PathBuf>> {
+        let mut builder = GlobSetBuilder::new();
+        for glob in globs {
+            builder.add(Glob::new(glob).with_context(|| format!("Invalid glob: `{glob}`"))?);
+        }
+        let glob_set = builder.build()?;
+
+        let files = WalkBuilder::new(&self.root)
+            .standard_filters(false)
+            .filter_entry(|entry| {
+                !matches!(entry.file_name().to_str(), Some("node_modules" | ".git"))
+            })
+            .build()
+            .filter_map(|entry| {
+                let path = entry.ok()?.into_path();
+                let rel_path = path.strip_prefix(&self.root).ok()?;
+                (path.is_file() && glob_set.is_match(rel_path)).then_some(path)
+            })
+            .collect();
+        Ok(files)
+    }
+
+    /// Returns the package's test globs, relative to its root.
+    pub fn test_globs(&self) -> Vec<String> {
+        match &self.manifest.config.test_files {
+            Some(globs) => globs.clone(),
+            None => vec![
+                "tests/**".to_string(),
+                "src/**/*.{test,spec}.{ts,tsx}".to_string(),
+            ],
+        }
+    }
+
+    /// Returns the files matching the package's test globs.
+    pub fn test_files(&self) -> Result<Vec<PathBuf>> {
+        self.glob_files(&self.test_globs())
+    }
 }
 
 impl Debug for Package {
@@ -342,16 +740,18 @@ impl Debug for Package {
     }
 }
 
-pub type PackageGraph = DepGraph<Package>;
+pub type PackageGraph = DepGraph<Package, DependencyKind>;
 
 pub fn build_package_graph(packages: &[Package], roots: &[Package]) -> Result<PackageGraph> {
-    DepGraph::build(
+    DepGraph::build_with_edges(
         roots.to_vec(),
         |pkg| pkg.name.to_string(),
         |pkg| {
-            pkg.all_dependencies()
-                .filter_map(|name| packages.iter().find(|other_pkg| other_pkg.name == name))
-                .cloned()
+            pkg.dependencies()
+                .filter_map(|(name, kind, _)| {
+                    let dep = packages.iter().find(|other_pkg| other_pkg.name == name)?;
+                    Some((dep.clone(), kind))
+                })
                 .collect()
         },
     )
@@ -416,4 +816,68 @@ mod test {
         assert!(dg.is_dependent_on(a, c));
         assert!(!dg.is_dependent_on(b, a));
     }
+
+    #[test]
+    fn test_package_graph_cycle() {
+        let pkgs = crate::test_packages! [
+          {"name": "a", "dependencies": {"b": "0.1.0"}},
+          {"name": "b", "dependencies": {"c": "0.1.0"}},
+          {"name": "c", "peerDependencies": {"a": "0.1.0"}},
+          {"name": "d", "dependencies": {"e": "0.1.0"}},
+          {"name": "e", "dependencies": {"d": "0.1.0"}}
+        ];
+
+        let Err(err) = build_package_graph(&pkgs, &pkgs) else {
+            panic!("expected a cycle")
+        };
+        assert_eq!(
+            err.to_string(),
+            "2 cycles detected in dependency graph:
+
+  a → b → c → a
+    a → b (dependencies)
+    b → c (dependencies)
+    c → a (peerDependencies)
+  hint: consider removing the dependency c → a (peerDependencies)
+
+  d → e → d
+    d → e (dependencies)
+    e → d (dependencies)
+  hint: consider removing the dependency e → d (dependencies)"
+        );
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn test_package_graph_dev_cycle() {
+        let pkgs = crate::test_packages! [
+          {"name": "lib", "devDependencies": {"test-utils": "0.1.0"}},
+          {"name": "test-utils", "dependencies": {"lib": "0.1.0"}}
+        ];
+
+        let [lib, test_utils] = &pkgs;
+
+        let dg = build_package_graph(&pkgs, std::slice::from_ref(lib)).unwrap();
+        assert_eq!(
+            dg.nodes().collect::<HashSet<_>>(),
+            hashset! {lib, test_utils}
+        );
+        assert_eq!(dg.all_deps_for(lib).count(), 0);
+        assert_eq!(
+            dg.all_deps_for(test_utils).collect::<HashSet<_>>(),
+            hashset! {lib}
+        );
+        assert!(dg.is_dependent_on(test_utils, lib));
+        assert!(!dg.is_dependent_on(lib, test_utils));
+    }
+
+    #[test]
+    fn test_is_asset_file() {
+        let [pkg] = crate::test_packages!({"name": "a"});
+        let root = Path::new("dummy.rs");
+        assert!(pkg.is_asset_file(&root.join("src/styles/main.css")));
+        assert!(pkg.is_asset_file(&root.join("src/assets/font.woff2")));
+        assert!(!pkg.is_asset_file(&root.join("src/lib.ts")));
+        assert!(!pkg.is_asset_file(&root.join("dist/main.css")));
+    }
 }
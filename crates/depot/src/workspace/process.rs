@@ -22,6 +22,7 @@ pub enum OutputChannel {
 }
 
 /// A string emitted by a shell command on a given [`OutputChannel`].
+#[derive(Clone)]
 pub struct LogLine {
     pub line: String,
     #[allow(unused)] // We may eventually want to distinguish stdout/stderr in the logs
@@ -30,6 +31,9 @@ pub struct LogLine {
 
 pub type LogBuffer = RingBuffer<LogLine>;
 
+/// Receives each line of output from a process, see [`Process::with_output_handler`].
+pub type OutputHandler = Arc<dyn Fn(LogLine) + Send + Sync>;
+
 /// Encapsulates shell commands.
 ///
 /// Wrapper around [`tokio::process::Command`] that deals with I/O.
@@ -39,13 +43,48 @@ pub struct Process {
     logs: Arc<Mutex<LogBuffer>>,
     finished: AtomicBool,
 
-    // TODO: is it necessary to abort these handles?
-    #[allow(unused)]
     pipe_handles: Mutex<Vec<JoinHandle<()>>>,
 }
 
 impl Process {
-    pub fn new(script: String, mut cmd: tokio::process::Command) -> Result<Self> {
+    pub fn new(script: String, cmd: tokio::process::Command) -> Result<Self> {
+        let logs: Arc<Mutex<LogBuffer>> = Arc::new(Mutex::new(RingBuffer::new()));
+        let handler: OutputHandler = {
+            let logs = logs.clone();
+            Arc::new(move |line| Self::append(&logs, line))
+        };
+        Self::spawn(script, cmd, logs, handler)
+    }
+
+    /// Spawns a process whose output is passed to `handler` rather than kept in its own logs.
+    pub fn with_output_handler(
+        script: String,
+        cmd: tokio::process::Command,
+        handler: OutputHandler,
+    ) -> Result<Self> {
+        let logs = Arc::new(Mutex::new(RingBuffer::new()));
+        Self::spawn(script, cmd, logs, handler)
+    }
+
+    /// Creates a process without a child, whose logs are written with [`Process::push_line`].
+    ///
+    /// Used to show a package's share of the output of a process that spans the workspace.
+    pub fn detached(script: String) -> Self {
+        Process {
+            script,
+            child: Mutex::new(None),
+            logs: Arc::new(Mutex::new(RingBuffer::new())),
+            finished: AtomicBool::new(false),
+            pipe_handles: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn spawn(
+        script: String,
+        mut cmd: tokio::process::Command,
+        logs: Arc<Mutex<LogBuffer>>,
+        handler: OutputHandler,
+    ) -> Result<Self> {
         cmd.kill_on_drop(true);
         cmd.stdout(Stdio::piped());
         cmd.stderr(Stdio::piped());
@@ -54,16 +93,15 @@ impl Process {
             .spawn()
             .with_context(|| format!("Failed to spawn process: `{script}`"))?;
 
-        let logs: Arc<Mutex<RingBuffer<LogLine>>> = Arc::new(Mutex::new(RingBuffer::new()));
         let pipe_handles = vec![
             tokio::spawn(Self::pipe_stdio(
                 child.stdout.take().unwrap(),
-                logs.clone(),
+                handler.clone(),
                 OutputChannel::Stdout,
             )),
             tokio::spawn(Self::pipe_stdio(
                 child.stderr.take().unwrap(),
-                logs.clone(),
+                handler,
                 OutputChannel::Stderr,
             )),
         ];
@@ -79,23 +117,39 @@ impl Process {
 
     async fn pipe_stdio(
         stdio: impl AsyncRead + Unpin,
-        buffer: Arc<Mutex<LogBuffer>>,
+        handler: OutputHandler,
         channel: OutputChannel,
     ) {
         let mut lines = BufReader::new(stdio).lines();
         while let Some(line) = lines.next_line().await.unwrap() {
-            let mut buffer = buffer.lock().unwrap();
-            let line = match line.strip_prefix("\u{1b}c") {
-                Some(rest) => {
-                    buffer.clear();
-                    rest.to_string()
-                }
-                None => line,
-            };
-            buffer.push(LogLine { line, channel });
+            handler(LogLine { line, channel });
         }
     }
 
+    /// Adds `line` to `buffer`, clearing it first if the line starts by resetting the terminal.
+    fn append(buffer: &Mutex<LogBuffer>, line: LogLine) {
+        let mut buffer = buffer.lock().unwrap();
+        let line = match line.line.strip_prefix("\u{1b}c") {
+            Some(rest) => {
+                buffer.clear();
+                LogLine {
+                    line: rest.to_string(),
+                    ..line
+                }
+            }
+            None => line,
+        };
+        buffer.push(line);
+    }
+
+    pub fn push_line(&self, line: LogLine) {
+        Self::append(&self.logs, line);
+    }
+
+    pub fn set_finished(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+    }
+
     pub fn script(&self) -> &str {
         &self.script
     }
@@ -116,6 +170,12 @@ impl Process {
             .await
             .with_context(|| format!("Process `{}` failed", self.script));
 
+        // Wait for the remaining output to be read so the logs are complete once the process is finished.
+        let pipe_handles = std::mem::take(&mut *self.pipe_handles.lock().unwrap());
+        for handle in pipe_handles {
+            let _ = handle.await;
+        }
+
         self.finished.store(true, Ordering::SeqCst);
 
         status_res
@@ -0,0 +1,160 @@
+use anyhow::{Context, Result, bail};
+use indexmap::IndexMap;
+
+pub const DEV_PROFILE: &str = "dev";
+pub const RELEASE_PROFILE: &str = "release";
+
+/// Build settings declared under "profiles" in the workspace's depot config.
+///
+/// Unset fields are inherited from the profile named by `inherits`, which defaults to `dev`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProfileConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inherits: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minify: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sourcemap: Option<bool>,
+
+    /// Vite mode, which selects `import.meta.env.MODE` and also loads the `.env.[mode]` files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+
+    /// Environment variables set for every build process.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub env: IndexMap<String, String>,
+
+    /// Global constants replaced at build time via Vite's `define`.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub define: IndexMap<String, serde_json::Value>,
+}
+
+/// A fully-resolved build profile.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+
+    /// True if the profile is `release` or inherits from it.
+    pub release: bool,
+
+    pub minify: bool,
+    pub sourcemap: bool,
+    pub mode: String,
+    pub env: IndexMap<String, String>,
+    pub define: IndexMap<String, serde_json::Value>,
+}
+
+impl Profile {
+    fn builtin(name: &str) -> Option<Self> {
+        let release = match name {
+            DEV_PROFILE => false,
+            RELEASE_PROFILE => true,
+            _ => return None,
+        };
+        Some(Profile {
+            name: name.to_string(),
+            release,
+            minify: release,
+            sourcemap: !release,
+            mode: if release { "production" } else { "development" }.to_string(),
+            env: IndexMap::new(),
+            define: IndexMap::new(),
+        })
+    }
+
+    /// Resolves the profile `name` against the profiles declared in the workspace config.
+    pub fn resolve(name: &str, configs: &IndexMap<String, ProfileConfig>) -> Result<Self> {
+        let mut chain = vec![name];
+        let mut base = loop {
+            let current = *chain.last().unwrap();
+            let config = configs.get(current);
+            let parent = match config.and_then(|config| config.inherits.as_deref()) {
+                Some(parent) => parent,
+                None => match Profile::builtin(current) {
+                    Some(profile) => break profile,
+                    None if config.is_some() => DEV_PROFILE,
+                    None => bail!("Unknown profile: `{current}`"),
+                },
+            };
+            if chain.contains(&parent) {
+                bail!("Profile `{parent}` inherits from itself");
+            }
+            chain.push(parent);
+        };
+
+        for name in chain.iter().rev() {
+            let Some(config) = configs.get(*name) else {
+                continue;
+            };
+            if let Some(minify) = config.minify {
+                base.minify = minify;
+            }
+            if let Some(sourcemap) = config.sourcemap {
+                base.sourcemap = sourcemap;
+            }
+            if let Some(mode) = &config.mode {
+                base.mode.clone_from(mode);
+            }
+            base.env.extend(config.env.clone());
+            base.define.extend(config.define.clone());
+        }
+        base.name = name.to_string();
+
+        Ok(base)
+    }
+
+    /// Vite `define` entries as a JSON object mapping each constant to the JS expression replacing it.
+    pub fn define_json(&self) -> Result<String> {
+        let exprs = self
+            .define
+            .iter()
+            .map(|(key, value)| Ok((key, serde_json::to_string(value)?)))
+            .collect::<Result<IndexMap<_, _>>>()?;
+        serde_json::to_string(&exprs).context("Failed to serialize defines")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_profile_resolve() {
+        let configs: IndexMap<String, ProfileConfig> = serde_json::from_value(serde_json::json!({
+            "release": { "sourcemap": true },
+            "staging": {
+                "inherits": "release",
+                "mode": "staging",
+                "define": { "__API_URL__": "https://staging.example.com" }
+            },
+            "debug": {},
+            "loop": { "inherits": "loop" }
+        }))
+        .unwrap();
+
+        let dev = Profile::resolve("dev", &configs).unwrap();
+        assert!(!dev.release && !dev.minify && dev.sourcemap);
+        assert_eq!(dev.mode, "development");
+
+        let release = Profile::resolve("release", &configs).unwrap();
+        assert!(release.release && release.minify && release.sourcemap);
+
+        let staging = Profile::resolve("staging", &configs).unwrap();
+        assert!(staging.release && staging.sourcemap);
+        assert_eq!(staging.mode, "staging");
+        assert_eq!(
+            staging.define_json().unwrap(),
+            r#"{"__API_URL__":"\"https://staging.example.com\""}"#
+        );
+
+        let debug = Profile::resolve("debug", &configs).unwrap();
+        assert_eq!(debug.name, "debug");
+        assert!(!debug.release);
+
+        assert!(Profile::resolve("loop", &configs).is_err());
+        assert!(Profile::resolve("missing", &configs).is_err());
+    }
+}
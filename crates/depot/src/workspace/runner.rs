@@ -4,9 +4,13 @@ use futures::{FutureExt, future::BoxFuture};
 use log::debug;
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     future::Future,
-    sync::{Arc, atomic::Ordering},
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 use tokio::sync::Notify;
 
@@ -18,6 +22,7 @@ use crate::{
 use super::{
     Command, CommandGraph, CommandInner, CommandRuntime, Workspace, build_command_graph,
     dep_graph::DepGraph,
+    package::{DependencyKind, Package},
 };
 
 #[atomic_enum::atomic_enum]
@@ -33,26 +38,38 @@ type TaskFuture = Box<dyn FnOnce() -> BoxFuture<'static, (Result<()>, Task)>>;
 pub struct TaskInner {
     key: String,
     command: Command,
+    package: Option<Package>,
     deps: Vec<String>,
+    input_files: Option<Vec<PathBuf>>,
     status: AtomicTaskStatus,
+    fresh: bool,
     can_skip: bool,
+    interface_unchanged: AtomicBool,
 }
 
 shareable!(Task, TaskInner);
 
 impl Task {
+    #[allow(clippy::too_many_arguments)]
     fn make<F: Future<Output = Result<()>> + Send + 'static>(
         key: String,
         command: Command,
+        package: Option<Package>,
         fut: F,
         deps: Vec<String>,
+        input_files: Option<Vec<PathBuf>>,
+        fresh: bool,
         can_skip: bool,
     ) -> (Self, TaskFuture) {
         let task = Task::new(TaskInner {
             key,
             command,
+            package,
             deps,
+            input_files,
+            fresh,
             can_skip,
+            interface_unchanged: AtomicBool::new(false),
             status: AtomicTaskStatus::new(TaskStatus::Pending),
         });
         let task2 = task.clone();
@@ -68,16 +85,45 @@ impl Task {
 }
 
 impl TaskInner {
-    fn key(&self) -> &str {
+    pub fn key(&self) -> &str {
         &self.key
     }
 
+    pub fn command(&self) -> &Command {
+        &self.command
+    }
+
+    /// The package this task runs on, or `None` for workspace tasks.
+    pub fn package(&self) -> Option<&Package> {
+        self.package.as_ref()
+    }
+
+    /// Files that determine whether the task needs to be re-run, or `None` if it always runs.
+    pub fn input_files(&self) -> Option<&[PathBuf]> {
+        self.input_files.as_deref()
+    }
+
+    /// Returns true if the task's input files have not changed since it last ran.
+    pub fn is_fresh(&self) -> bool {
+        self.fresh
+    }
+
     fn status(&self) -> TaskStatus {
         self.status.load(Ordering::SeqCst)
     }
+
+    /// Returns true if `dep` re-ran without changing anything this task relies on (early cutoff).
+    fn cut_off_from(&self, dep: &Task) -> bool {
+        dep.interface_unchanged.load(Ordering::SeqCst)
+            && self.command == dep.command
+            && self
+                .package
+                .as_ref()
+                .is_some_and(|pkg| self.command.uses_dep_interfaces(pkg))
+    }
 }
 
-type TaskGraph = DepGraph<Task>;
+pub type TaskGraph = DepGraph<Task>;
 
 impl Workspace {
     fn spawn_log_thread(
@@ -113,33 +159,75 @@ impl Workspace {
         })
     }
 
+    /// Returns the roots and the packages they depend on. If `dev` is true, this also includes
+    /// the dev dependencies of those packages and everything that they depend on.
+    fn task_packages(&self, dev: bool) -> Vec<Package> {
+        let closure = |pkgs: Vec<Package>| {
+            let mut seen = HashSet::new();
+            pkgs.iter()
+                .flat_map(|pkg| self.pkg_graph.all_deps_for(pkg).chain([pkg]))
+                .filter(|pkg| seen.insert(pkg.name.clone()))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        let packages = closure(self.roots.clone());
+        if !dev {
+            return packages;
+        }
+        let dev_deps = packages.iter().flat_map(|pkg| {
+            self.pkg_graph
+                .immediate_edges_for(pkg)
+                .filter(|(_, kind)| **kind == DependencyKind::Dev)
+                .map(|(dep, _)| dep.clone())
+        });
+        closure(packages.iter().cloned().chain(dev_deps).collect())
+    }
+
     fn build_task_graph(
         &self,
         cmd_graph: &CommandGraph,
         runtime: Option<CommandRuntime>,
     ) -> (TaskGraph, HashMap<Task, TaskFuture>) {
+        // Commands like `test` only run on the selected packages, but the commands they depend on
+        // (like `build`) also run on the dev dependencies of those packages. Since a task depends on
+        // every task of the commands its command depends on, tests then wait on those builds.
+        let packages = self.task_packages(false);
+        let uses_dev_deps = cmd_graph.nodes().any(|cmd| cmd.uses_dev_deps());
+        let packages_with_dev_deps = self.task_packages(uses_dev_deps);
+
         let futures = RefCell::new(HashMap::new());
         let task_pool = RefCell::new(HashMap::new());
 
         let tasks_for = |cmd: &Command| -> Vec<Task> {
             macro_rules! add_task {
-                ($key:expr, $task:expr, $deps:expr, $files:expr) => {{
+                ($key:expr, $pkg:expr, $task:expr, $deps:expr, $files:expr) => {{
                     task_pool
                         .borrow_mut()
                         .entry($key.clone())
                         .or_insert_with(|| {
+                            let files: Option<Vec<PathBuf>> = $files;
+                            let fresh = match &files {
+                                Some(files) => {
+                                    let fingerprints = self.fingerprints.read().unwrap();
+                                    fingerprints.can_skip(&$key, files.iter().cloned())
+                                }
+                                None => false,
+                            };
                             let can_skip = self.common.incremental
                                 && !matches!(runtime, Some(CommandRuntime::RunForever))
-                                && match $files {
-                                    Some(files) => {
-                                        let fingerprints = self.fingerprints.read().unwrap();
-                                        fingerprints.can_skip(&$key, files)
-                                    }
-                                    None => false,
-                                };
-
-                            let (task, future) =
-                                Task::make($key, cmd.clone(), $task, $deps, can_skip);
+                                && fresh;
+
+                            let (task, future) = Task::make(
+                                $key,
+                                cmd.clone(),
+                                $pkg,
+                                $task,
+                                $deps,
+                                files,
+                                fresh,
+                                can_skip,
+                            );
                             futures.borrow_mut().insert(task.clone(), future);
                             task
                         })
@@ -148,11 +236,15 @@ impl Workspace {
             }
 
             match &**cmd {
-                CommandInner::Package(pkg_cmd) => self
-                    .roots
-                    .iter()
-                    .flat_map(|pkg| {
-                        self.pkg_graph.all_deps_for(pkg).chain([pkg]).map(|pkg| {
+                CommandInner::Package(pkg_cmd) => {
+                    let packages = if pkg_cmd.uses_dev_deps() {
+                        &packages
+                    } else {
+                        &packages_with_dev_deps
+                    };
+                    packages
+                        .iter()
+                        .map(|pkg| {
                             let pkg = pkg.clone();
                             let key = pkg_cmd.pkg_key(&pkg);
                             let deps = self
@@ -160,17 +252,23 @@ impl Workspace {
                                 .immediate_deps_for(&pkg)
                                 .map(|pkg| pkg_cmd.pkg_key(pkg))
                                 .collect();
-                            let files = pkg.all_files().collect::<Vec<_>>();
-                            add_task!(key, cmd.clone().run_pkg(pkg), deps, Some(files))
+                            let files = pkg_cmd.input_files(&pkg);
+                            add_task!(
+                                key,
+                                Some(pkg.clone()),
+                                cmd.clone().run_pkg(pkg),
+                                deps,
+                                files
+                            )
                         })
-                    })
-                    .collect(),
+                        .collect()
+                }
                 CommandInner::Workspace(ws_cmd) => {
                     let this = self.clone();
                     let key = ws_cmd.ws_key();
                     let deps = vec![];
                     let files = ws_cmd.input_files(self);
-                    vec![add_task!(key, cmd.clone().run_ws(this), deps, files)]
+                    vec![add_task!(key, None, cmd.clone().run_ws(this), deps, files)]
                 }
             }
         };
@@ -195,7 +293,20 @@ impl Workspace {
         (task_graph, futures.into_inner())
     }
 
+    /// Builds the graph of tasks that would be executed by running `root`.
+    pub fn task_graph(&self, root: &Command) -> TaskGraph {
+        let cmd_graph = build_command_graph(root);
+        let (task_graph, _) = self.build_task_graph(&cmd_graph, root.runtime());
+        task_graph
+    }
+
     pub async fn run(&self, root: Command) -> Result<()> {
+        self.run_until(root, std::future::pending()).await
+    }
+
+    /// Runs `root` until it completes or `cancel` resolves, in which case all running tasks are stopped.
+    pub async fn run_until(&self, root: Command, cancel: impl Future<Output = ()>) -> Result<()> {
+        tokio::pin!(cancel);
         let runtime = root.runtime();
         let cmd_graph = build_command_graph(&root);
         let (task_graph, mut task_futures) = self.build_task_graph(&cmd_graph, runtime);
@@ -209,11 +320,13 @@ impl Workspace {
         let cleanup_logs = self.spawn_log_thread(&log_should_exit, &runner_should_exit, runtime);
 
         let mut running_futures = Vec::new();
-        let result = loop {
+        let mut completed = false;
+        let mut result = loop {
             let finished = task_graph
                 .nodes()
                 .all(|task| task.status() == TaskStatus::Finished);
             if finished {
+                completed = true;
                 break Ok(());
             }
 
@@ -226,7 +339,10 @@ impl Workspace {
                     .iter()
                     .all(|dep| dep.status() == TaskStatus::Finished);
                 if deps_finished {
-                    let can_skip = task.can_skip && imm_deps.iter().all(|dep| dep.can_skip);
+                    let can_skip = task.can_skip
+                        && imm_deps
+                            .iter()
+                            .all(|dep| dep.can_skip || task.cut_off_from(dep));
                     let task_fut = task_futures.remove(task).unwrap();
                     if can_skip {
                         task.status.store(TaskStatus::Finished, Ordering::SeqCst);
@@ -245,6 +361,7 @@ impl Workspace {
             let one_output = futures::future::select_all(&mut running_futures);
             let (result, idx, _) = tokio::select! { biased;
               () = &mut runner_should_exit_fut => break Ok(()),
+              () = &mut cancel => break Ok(()),
               output = one_output => output,
             };
 
@@ -260,10 +377,17 @@ impl Workspace {
             completed_task
                 .status
                 .store(TaskStatus::Finished, Ordering::SeqCst);
-            self.fingerprints
-                .write()
-                .unwrap()
-                .update_time(completed_task.key().to_string());
+            let mut fingerprints = self.fingerprints.write().unwrap();
+            fingerprints.update_time(completed_task.key().to_string());
+            if let Some(pkg) = completed_task.package()
+                && let Some(hash) = completed_task.command.interface_hash(pkg)
+            {
+                let unchanged =
+                    fingerprints.update_interface(completed_task.key().to_string(), hash);
+                completed_task
+                    .interface_unchanged
+                    .store(unchanged, Ordering::SeqCst);
+            }
         };
 
         for fut in &mut running_futures {
@@ -278,6 +402,14 @@ impl Workspace {
         log_should_exit.notify_one();
         cleanup_logs.await;
 
+        if completed {
+            result = root.finish(self);
+        }
+
+        if let Some(summary) = root.summary() {
+            println!("{summary}");
+        }
+
         if root.name() != "clean" {
             self.fingerprints.read().unwrap().save(&self.root)?;
         }
@@ -0,0 +1,23 @@
+use depot_test_utils::project;
+
+#[test]
+fn baseline() {
+    let p = project();
+    p.file(
+        "bench/add.bench.ts",
+        r#"
+import { add } from "bar";
+
+bench("add", () => { add(1, 2); });
+  "#,
+    );
+    let output = p.depot("bench --save-baseline base");
+    assert!(output.stdout().contains("Benchmark results:"));
+    assert!(p.exists(".depot/bench/baselines/base/bar.json"));
+
+    let output = p.depot("bench --baseline base");
+    assert!(output.stdout().contains("compared to `base`"));
+
+    assert!(p.maybe_depot("bench --baseline missing").is_err());
+    assert!(p.maybe_depot("bench --fail-on-regression").is_err());
+}
@@ -80,6 +80,14 @@ fn release() {
     assert!(!p.exists("dist/lib.js.map"));
 }
 
+#[test]
+fn release_nested() {
+    let p = project();
+    assert!(p.maybe_depot("watch build --release").is_err());
+    assert!(p.maybe_depot("graph --tasks 'build --release'").is_err());
+    p.depot("--profile release graph --tasks 'build --release'");
+}
+
 #[test]
 fn workspace_() {
     let ws = workspace();
@@ -102,6 +110,92 @@ fn workspace_() {
     assert!(ws.exists("packages/bar/dist/lib.js"));
 }
 
+#[test]
+fn project_references() {
+    let ws = workspace();
+    ws.depot("new foo");
+    ws.depot("new bar");
+    ws.file(
+        "packages/bar/package.json",
+        r#"{
+  "dependencies": {"foo": "workspace:^0.1.0"},
+  "depot": {"platform": "browser"}
+}"#,
+    );
+
+    let mut root: serde_json::Value = serde_json::from_str(&ws.read("package.json")).unwrap();
+    root["depot"]["project-references"] = serde_json::json!(true);
+    ws.file("package.json", serde_json::to_string_pretty(&root).unwrap());
+
+    ws.depot("init -- --no-frozen-lockfile");
+    ws.depot("build");
+    assert!(ws.exists("packages/bar/dist/lib.js"));
+
+    let tsconfig: serde_json::Value =
+        serde_json::from_str(&ws.read("packages/bar/node_modules/.depot/tsconfig.dev.json"))
+            .unwrap();
+    assert_eq!(
+        tsconfig["references"],
+        serde_json::json!([{ "path": "../../../foo/node_modules/.depot/tsconfig.dev.json" }])
+    );
+    assert_eq!(tsconfig["extends"], "../../tsconfig.json");
+    assert!(!ws.read("packages/bar/tsconfig.json").contains("references"));
+
+    ws.file("packages/foo/src/lib.ts", "export let x: number = \"\";");
+    assert!(ws.maybe_depot("build").is_err());
+}
+
+#[test]
+fn early_cutoff() {
+    let ws = workspace();
+    ws.depot("new foo");
+    ws.depot("new bar");
+    ws.file(
+        "packages/bar/package.json",
+        r#"{
+  "dependencies": {"foo": "workspace:^0.1.0"},
+  "depot": {"platform": "browser"}
+}"#,
+    );
+    ws.file("packages/foo/src/lib.ts", "export let x = (): number => 1;");
+    ws.depot("init -- --no-frozen-lockfile");
+    ws.depot("--incremental build");
+
+    let modified = || {
+        std::fs::metadata(ws.root().join("packages/bar/dist/lib.js"))
+            .unwrap()
+            .modified()
+            .unwrap()
+    };
+    let before = modified();
+
+    // Changing an implementation without changing the declarations doesn't rebuild dependents.
+    ws.file("packages/foo/src/lib.ts", "export let x = (): number => 2;");
+    ws.depot("--incremental build");
+    assert_eq!(modified(), before);
+
+    ws.file(
+        "packages/foo/src/lib.ts",
+        "export let x = (): string => \"2\";",
+    );
+    ws.depot("--incremental build");
+    assert_ne!(modified(), before);
+}
+
+#[test]
+fn incremental_profile_switch() {
+    let p = project();
+    p.depot("--incremental build");
+    assert!(p.exists("dist/lib.js.map"));
+
+    // Switching profiles keeps the build info of each profile, but re-emits the outputs.
+    p.depot("--incremental build --release");
+    assert!(!p.read("dist/lib.js").contains("sourceMappingURL"));
+    p.depot("--incremental build");
+    assert!(p.exists("node_modules/.depot/tsbuildinfo/release/esm.tsbuildinfo"));
+    assert!(p.read("dist/lib.js").contains("sourceMappingURL"));
+}
+
 #[test]
 fn lint_basic() {
     let p = project();
@@ -164,3 +258,68 @@ fn vike() {
     let p = custom_project_for("site", "browser", "--react --vike");
     p.depot("build --lint-fail");
 }
+
+#[test]
+fn size_report() {
+    let p = project_for("script", "browser");
+    let output = p.depot("build");
+    assert!(output.stdout().contains("Bundle sizes for foo:"));
+    assert!(output.stdout().contains("dist/foo.iife.js"));
+
+    let mut pkg: serde_json::Value = serde_json::from_str(&p.read("package.json")).unwrap();
+    pkg["depot"]["size-limit"] = serde_json::json!({ "dist/*.js": "1B" });
+    p.file("package.json", serde_json::to_string_pretty(&pkg).unwrap());
+    std::fs::remove_file(p.root().join("node_modules/.depot/sizes.json")).unwrap();
+    assert!(p.maybe_depot("build").is_err());
+    // A build over budget doesn't become the baseline.
+    assert!(!p.exists("node_modules/.depot/sizes.json"));
+}
+
+#[test]
+fn lib_cjs() {
+    let p = project_for("lib", "node");
+    p.file("src/util.ts", "export let double = (x: number) => x * 2;");
+    p.file(
+        "src/lib.ts",
+        "import { double } from \"./util.js\";\nexport let quadruple = (x: number) => double(double(x));",
+    );
+
+    let mut pkg: serde_json::Value = serde_json::from_str(&p.read("package.json")).unwrap();
+    pkg["depot"]["formats"] = serde_json::json!(["esm", "cjs"]);
+    p.file("package.json", serde_json::to_string_pretty(&pkg).unwrap());
+    assert!(p.maybe_depot("build").is_err());
+
+    pkg["exports"] = serde_json::json!({
+      ".": {
+        "import": { "types": "./dist/lib.d.ts", "default": "./dist/lib.js" },
+        "require": { "types": "./dist/lib.d.cts", "default": "./dist/lib.cjs" }
+      }
+    });
+    p.file("package.json", serde_json::to_string_pretty(&pkg).unwrap());
+    p.depot("build");
+    assert!(p.exists("dist/lib.js"));
+    assert!(p.exists("dist/lib.cjs"));
+    assert!(p.exists("dist/lib.d.cts"));
+    assert!(p.read("dist/lib.cjs").contains("require(\"./util.cjs\")"));
+    // Outputs of deleted sources are removed.
+    std::fs::remove_file(p.root().join("src/util.ts")).unwrap();
+    p.file("src/lib.ts", "export let quadruple = (x: number) => x * 4;");
+    p.depot("build");
+    assert!(p.exists("dist/lib.cjs"));
+    assert!(!p.exists("dist/util.cjs"));
+    assert!(!p.exists("dist/util.d.cts"));
+}
+
+#[test]
+fn lib_generated_exports() {
+    let p = project_for("lib", "node");
+    let mut pkg: serde_json::Value = serde_json::from_str(&p.read("package.json")).unwrap();
+    pkg["depot"]["formats"] = serde_json::json!(["esm", "cjs"]);
+    pkg.as_object_mut().unwrap().remove("exports");
+    p.file("package.json", serde_json::to_string_pretty(&pkg).unwrap());
+    p.depot("build");
+
+    let pkg: serde_json::Value = serde_json::from_str(&p.read("package.json")).unwrap();
+    assert_eq!(pkg["exports"]["."]["require"]["default"], "./dist/lib.cjs");
+    assert_eq!(pkg["exports"]["."]["import"]["default"], "./dist/lib.js");
+}
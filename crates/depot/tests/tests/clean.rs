@@ -17,3 +17,54 @@ fn workspace() {
     ws.depot("clean");
     assert!(!ws.exists("packages/bar/dist"));
 }
+
+#[test]
+fn tsbuildinfo() {
+    let p = project();
+    p.depot("--incremental build");
+    assert!(p.exists("node_modules/.depot/tsbuildinfo/dev/esm.tsbuildinfo"));
+    p.depot("--incremental build --release");
+    assert!(p.exists("node_modules/.depot/tsbuildinfo/release/esm.tsbuildinfo"));
+    p.depot("clean");
+    assert!(!p.exists("node_modules/.depot/tsbuildinfo"));
+}
+
+#[test]
+fn artifact_manifest() {
+    let p = project();
+    p.depot("build");
+    let manifest: serde_json::Value =
+        serde_json::from_str(&p.read("dist/.depot-manifest.json")).unwrap();
+    assert_eq!(manifest["profile"], "dev");
+    assert!(manifest["files"]["dist/lib.js"]["size"].as_u64().unwrap() > 0);
+
+    assert_eq!(
+        manifest["files"]["dist/lib.js"]["hash"]
+            .as_str()
+            .unwrap()
+            .len(),
+        64
+    );
+
+    // Only files produced by depot are removed.
+    p.file("dist/notes.txt", "keep me");
+    p.depot("clean");
+    assert!(!p.exists("dist/lib.js"));
+    assert!(!p.exists("dist/.depot-manifest.json"));
+    assert!(p.exists("dist/notes.txt"));
+}
+
+#[test]
+fn failed_build() {
+    let p = project();
+    p.depot("build");
+    assert!(p.exists("dist/.depot-manifest.json"));
+
+    // A failed build leaves outputs that no manifest lists, so all of `dist/` is removed.
+    p.file("src/lib.ts", "export let x: number = \"oops\";");
+    assert!(p.maybe_depot("build").is_err());
+    assert!(!p.exists("dist/.depot-manifest.json"));
+    p.file("dist/extra.js", "");
+    p.depot("clean");
+    assert!(!p.exists("dist"));
+}
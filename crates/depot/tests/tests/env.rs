@@ -0,0 +1,26 @@
+use depot_test_utils::workspace_single_lib;
+
+#[test]
+fn precedence() {
+    let ws = workspace_single_lib();
+    ws.file(".env", "VITE_A=ws\nVITE_B=ws\n");
+    ws.file(".env.development", "VITE_B=ws-dev\n");
+    ws.file("packages/bar/.env.local", "VITE_A=bar\n");
+
+    let output = ws.depot("env");
+    assert_eq!(output.stdout(), "VITE_A=ws\nVITE_B=ws-dev\n");
+
+    let output = ws.depot("-p bar env");
+    assert_eq!(output.stdout(), "VITE_A=bar\nVITE_B=ws-dev\n");
+}
+
+#[test]
+fn profile_files() {
+    let ws = workspace_single_lib();
+    ws.file(".env.production", "VITE_A=production\nVITE_B=production\n");
+    ws.file(".env.release", "VITE_B=release\n");
+    ws.file(".env.dev", "VITE_B=dev\n");
+
+    let output = ws.depot("--profile release env");
+    assert_eq!(output.stdout(), "VITE_A=production\nVITE_B=release\n");
+}
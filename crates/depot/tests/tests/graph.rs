@@ -0,0 +1,66 @@
+use depot_test_utils::{project, workspace_single_lib};
+
+#[test]
+fn basic() {
+    let p = project();
+    let output = p.depot("graph");
+    assert!(output.stdout().starts_with("digraph packages {"));
+}
+
+#[test]
+fn workspace() {
+    let ws = workspace_single_lib();
+    let output = ws.depot("graph --format json");
+    let graph: serde_json::Value = serde_json::from_str(output.stdout()).unwrap();
+    assert_eq!(graph["nodes"][0]["id"], "bar");
+    assert_eq!(graph["nodes"][0]["target"], "lib");
+
+    let output = ws.depot("graph --tasks test --format mermaid");
+    assert!(output.stdout().contains("test-bar"));
+    assert!(output.stdout().contains("build-bar"));
+}
+
+#[test]
+fn dev_dependencies() {
+    let ws = depot_test_utils::workspace();
+    ws.depot("new foo");
+    ws.depot("new bar");
+    ws.file(
+        "packages/bar/package.json",
+        r#"{
+  "devDependencies": {"foo": "workspace:^0.1.0"},
+  "depot": {"platform": "browser"}
+}"#,
+    );
+
+    let task_graph = |command: &str| {
+        let output = ws.depot(format!("-p bar graph --tasks {command} --format json"));
+        let graph: serde_json::Value = serde_json::from_str(output.stdout()).unwrap();
+        let nodes = graph["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|node| node["id"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        let edges = graph["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|edge| {
+                let id = |key: &str| edge[key].as_str().unwrap().to_string();
+                (id("from"), id("to"))
+            })
+            .collect::<Vec<_>>();
+        (nodes, edges)
+    };
+
+    // Building bar doesn't need its dev dependencies.
+    let (nodes, _) = task_graph("build");
+    assert!(nodes.contains(&"build-bar".to_string()));
+    assert!(!nodes.contains(&"build-foo".to_string()));
+
+    // Testing bar waits on the build of its dev dependency, without testing it.
+    let (nodes, edges) = task_graph("test");
+    assert!(!nodes.contains(&"test-foo".to_string()));
+    assert!(edges.contains(&("test-bar".to_string(), "build-foo".to_string())));
+}
@@ -1,7 +1,14 @@
+mod bench;
 mod build;
 mod clean;
 mod doc;
+mod env;
 mod fix;
 mod fmt;
+mod graph;
+mod metadata;
 mod new;
+mod run;
+mod task;
 mod test;
+mod watch;
@@ -0,0 +1,33 @@
+use depot_test_utils::workspace_single_lib;
+
+#[test]
+fn workspace() {
+    let ws = workspace_single_lib();
+    let output = ws.depot("metadata --format-version 1");
+    let metadata: serde_json::Value = serde_json::from_str(output.stdout()).unwrap();
+    assert_eq!(metadata["version"], 1);
+    assert_eq!(metadata["monorepo"], true);
+
+    let pkg = &metadata["packages"][0];
+    assert_eq!(pkg["name"], "bar");
+    assert_eq!(pkg["target"], "lib");
+    assert!(
+        pkg["entry_points"][0]
+            .as_str()
+            .unwrap()
+            .ends_with("src/lib.ts")
+    );
+    assert!(
+        pkg["source_files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|file| file.as_str().unwrap().ends_with("tests/add.test.ts"))
+    );
+}
+
+#[test]
+fn unsupported_version() {
+    let ws = workspace_single_lib();
+    assert!(ws.maybe_depot("metadata --format-version 2").is_err());
+}
@@ -0,0 +1,21 @@
+use depot_test_utils::workspace_single_lib;
+
+#[test]
+fn basic() {
+    let ws = workspace_single_lib();
+    ws.depot("new baz");
+
+    let manifest = "packages/bar/package.json";
+    let mut pkg: serde_json::Value = serde_json::from_str(&ws.read(manifest)).unwrap();
+    pkg["scripts"]["generate"] =
+        "node -e \"require('fs').writeFileSync(process.argv[1], '')\"".into();
+    ws.file(manifest, serde_json::to_string_pretty(&pkg).unwrap());
+
+    let output = ws.depot("run generate -- generated.txt");
+    assert!(ws.exists("packages/bar/generated.txt"));
+    assert!(
+        output
+            .stdout()
+            .contains("Skipped packages without a `generate` script: baz")
+    );
+}
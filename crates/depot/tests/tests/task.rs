@@ -0,0 +1,57 @@
+use depot_test_utils::{project, workspace_single_lib};
+
+fn add_tasks(p: &depot_test_utils::ProjectBuilder, manifest: &str, tasks: serde_json::Value) {
+    let mut pkg: serde_json::Value = serde_json::from_str(&p.read(manifest)).unwrap();
+    pkg["depot"]["tasks"] = tasks;
+    p.file(manifest, serde_json::to_string_pretty(&pkg).unwrap());
+}
+
+#[test]
+fn basic() {
+    let p = project();
+    add_tasks(
+        &p,
+        "package.json",
+        serde_json::json!({
+            "codegen": {
+                "command": "node -e \"require('fs').writeFileSync('src/gen.ts', 'export let x = 1;')\"",
+                "outputs": ["src/gen.ts"]
+            }
+        }),
+    );
+    p.depot("task codegen");
+    assert_eq!(p.read("src/gen.ts"), "export let x = 1;");
+}
+
+#[test]
+fn deps() {
+    let ws = workspace_single_lib();
+    add_tasks(
+        &ws,
+        "packages/bar/package.json",
+        serde_json::json!({
+            "check-dist": {
+                "command": "node -e \"require('fs').accessSync('dist/lib.js')\"",
+                "deps": ["build"]
+            }
+        }),
+    );
+    ws.depot("task check-dist");
+    assert!(ws.exists("packages/bar/dist/lib.js"));
+}
+
+#[test]
+fn errors() {
+    let p = project();
+    assert!(p.maybe_depot("task missing").is_err());
+
+    add_tasks(
+        &p,
+        "package.json",
+        serde_json::json!({
+            "a": { "command": "node -e 0", "deps": ["b"] },
+            "b": { "command": "node -e 0", "deps": ["a"] }
+        }),
+    );
+    assert!(p.maybe_depot("task a").is_err());
+}
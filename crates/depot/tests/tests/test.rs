@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use depot_test_utils::{project, workspace_single_lib};
 
 #[test]
@@ -42,3 +44,206 @@ test("add", () => expect(add(1, 2)).toBe(100))
     );
     ws.depot("test");
 }
+
+#[test]
+fn summary() {
+    let p = project();
+    let output = p.depot("test");
+    assert!(output.stdout().contains("Test results:"));
+
+    p.file(
+        "tests/fail.test.ts",
+        r#"
+import { add } from "bar";
+
+test("add", () => expect(add(1, 2)).toBe(100))
+  "#,
+    );
+    let err = p.maybe_depot("test").err().unwrap().to_string();
+    assert!(err.contains("tests/fail.test.ts:4 > add"));
+    assert!(err.contains("expected 3 to be 100"));
+}
+
+#[test]
+fn junit() {
+    let ws = workspace_single_lib();
+    ws.depot("init -- --no-frozen-lockfile");
+    ws.depot("test --junit report.xml");
+    let xml = ws.read("report.xml");
+    assert!(xml.contains("<testsuite name=\"bar\""));
+    assert!(xml.contains("failures=\"0\""));
+
+    // A run where every package is skipped still writes a valid, empty report.
+    std::fs::remove_file(ws.root().join("report.xml")).unwrap();
+    ws.depot("--incremental test --junit report.xml");
+    ws.depot("--incremental test --junit report.xml");
+    assert!(ws.read("report.xml").contains("<testsuites name=\"depot\""));
+}
+
+#[test]
+fn coverage() {
+    let p = project();
+    let output = p.depot("test --coverage");
+    assert!(output.stdout().contains("Coverage:"));
+    assert!(p.read("coverage/lcov.info").contains("SF:src/lib.ts"));
+    assert!(p.exists("coverage/index.html"));
+
+    let mut pkg: serde_json::Value = serde_json::from_str(&p.read("package.json")).unwrap();
+    pkg["depot"]["coverage-thresholds"] = serde_json::json!({ "lines": 100.1 });
+    p.file("package.json", serde_json::to_string_pretty(&pkg).unwrap());
+    assert!(p.maybe_depot("test --coverage").is_err());
+}
+
+#[test]
+fn coverage_missing_provider() {
+    let p = project();
+    std::fs::remove_dir_all(p.root().join("node_modules/@vitest/coverage-v8")).unwrap();
+    let err = p.maybe_depot("test --coverage").err().unwrap().to_string();
+    assert!(err.contains("pnpm add -D @vitest/coverage-v8"));
+}
+
+#[test]
+fn shard() {
+    let ws = workspace_single_lib();
+    ws.depot("init -- --no-frozen-lockfile");
+    let ran = ["1/2", "2/2"]
+        .into_iter()
+        .filter(|shard| {
+            ws.depot(format!("test --shard {shard}"))
+                .stdout()
+                .contains("Test results:")
+        })
+        .count();
+    assert_eq!(ran, 1);
+    assert!(ws.maybe_depot("test --shard 3/2").is_err());
+}
+
+#[test]
+fn shard_shared_durations() {
+    let ws = workspace_single_lib();
+    ws.depot("new baz");
+    ws.depot("init -- --no-frozen-lockfile");
+    ws.depot("test --durations measured.json");
+    assert!(ws.read("measured.json").contains("baz"));
+    ws.file("test-durations.json", r#"{"bar": 100, "baz": 100}"#);
+
+    // Each machine has its own local history, which must not change the assignment.
+    let local = "node_modules/.depot/test-durations.json";
+    ws.file(local, r#"{"bar": 100000, "baz": 1}"#);
+    ws.depot("test --shard 1/2 --junit shard1.xml");
+    std::fs::remove_file(ws.root().join(local)).unwrap();
+    ws.depot("test --shard 2/2 --junit shard2.xml");
+
+    let reports = [ws.read("shard1.xml"), ws.read("shard2.xml")];
+    for pkg in ["bar", "baz"] {
+        let suite = format!("<testsuite name=\"{pkg}\"");
+        let runs = reports.iter().filter(|xml| xml.contains(&suite)).count();
+        assert_eq!(runs, 1, "{pkg} ran on {runs} shards");
+    }
+}
+
+#[test]
+fn colocated() {
+    let p = project();
+    std::fs::remove_dir_all(p.root().join("tests")).unwrap();
+    let output = p.depot("test");
+    assert!(output.stdout().contains("no tests"));
+
+    p.file(
+        "src/add.test.ts",
+        r#"
+import { expect, test } from "vitest";
+
+import { add } from "./lib";
+
+test("add", () => expect(add(1, 2)).toBe(100))
+  "#,
+    );
+    assert!(p.maybe_depot("test").is_err());
+}
+
+#[test]
+fn watch_dependency() {
+    let ws = depot_test_utils::workspace();
+    ws.depot("new a");
+    ws.depot("new b");
+    ws.file(
+        "packages/b/package.json",
+        r#"{
+  "dependencies": {"a": "workspace:^0.1.0"},
+  "depot": {"platform": "browser"}
+}"#,
+    );
+    ws.file("packages/a/src/lib.ts", "export let x = 1;");
+    ws.file(
+        "packages/b/tests/x.test.ts",
+        r#"
+import fs from "node:fs";
+import { test } from "vitest";
+import { x } from "a";
+
+test("x", () => fs.writeFileSync("x.txt", String(x)));
+"#,
+    );
+    ws.depot("init -- --no-frozen-lockfile");
+
+    let _watch = ws.spawn_depot("--no-fullscreen test -w");
+    let timeout = Duration::from_secs(60);
+    ws.wait_for_file("packages/b/x.txt", timeout, |s| s == "1");
+
+    // Rebuilding the library re-runs the tests that depend on it.
+    ws.file("packages/a/src/lib.ts", "export let x = 2;");
+    ws.wait_for_file("packages/b/x.txt", timeout, |s| s == "2");
+}
+
+#[test]
+fn custom_test_files() {
+    let p = project();
+    p.file(
+        "tests/fail.test.ts",
+        r#"
+import { expect, test } from "vitest";
+
+test("fail", () => expect(1).toBe(2))
+  "#,
+    );
+
+    let mut manifest: serde_json::Value = serde_json::from_str(&p.read("package.json")).unwrap();
+    manifest["depot"]["test-files"] = serde_json::json!(["tests/fixtures/**"]);
+    p.file(
+        "package.json",
+        serde_json::to_string_pretty(&manifest).unwrap(),
+    );
+    p.file("tests/fixtures/data.json", "{}");
+    let output = p.depot("test");
+    assert!(output.stdout().contains("no tests"));
+
+    manifest["depot"]["test-files"] = serde_json::json!(["tests/add.test.ts"]);
+    p.file(
+        "package.json",
+        serde_json::to_string_pretty(&manifest).unwrap(),
+    );
+    p.depot("test");
+
+    manifest["depot"]["test-files"] = serde_json::json!(["tests/*.test.ts"]);
+    p.file(
+        "package.json",
+        serde_json::to_string_pretty(&manifest).unwrap(),
+    );
+    assert!(p.maybe_depot("test").is_err());
+}
+
+#[test]
+fn vitest_filter() {
+    let p = project();
+    p.file(
+        "tests/fail.test.ts",
+        r#"
+import { expect, test } from "vitest";
+
+test("fail", () => expect(1).toBe(2))
+  "#,
+    );
+    p.depot("test -- add");
+    assert!(p.maybe_depot("test -- fail").is_err());
+}
@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+use depot_test_utils::project;
+
+#[test]
+fn rejects_run_forever() {
+    let p = project();
+    assert!(p.maybe_depot("watch build --watch").is_err());
+}
+
+#[test]
+fn reruns_on_change() {
+    let p = project();
+    let _watch = p.spawn_depot("watch build");
+    let timeout = Duration::from_secs(60);
+    p.wait_for_file("dist/lib.js", timeout, |_| true);
+
+    p.file("src/lib.ts", "export let changed = true;");
+    p.wait_for_file("dist/lib.js", timeout, |s| s.contains("changed"));
+}